@@ -108,7 +108,7 @@ fn increment_atlas_index(
 
     for mut image_node in &mut image_nodes {
         if let Some(atlas) = &mut image_node.texture_atlas {
-            let mut new_index = atlas.index as i64 + inc;
+            let new_index = atlas.index as i64 + inc;
 
             if new_index < 0 {
                 atlas.index = 0;
@@ -1,10 +1,24 @@
 use crate::equation;
-use crate::equation::Symbol;
+use crate::equation::{Bounds, Equation, GridEquation, Symbol};
+use crate::game_state::InGame;
+use crate::glyph_atlas::GlyphAtlas;
+use crate::hint::ActiveHint;
+use crate::history::{Action, ActionKind, Edit, History};
+use crate::solver::HiddenCells;
+use crate::texture_manager::{TextureManager, TileKind};
+use bevy::asset::RenderAssetUsages;
 use bevy::math::prelude::*;
 use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// How far tiles stand out from the board plane. Their faces lie in it.
+pub const TILE_DEPTH: f32 = 10.0;
+// Width of the slanted edge around the face of a tile.
+const TILE_BEVEL: f32 = 5.0;
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Coordinates {
     pub x: i32,
     pub y: i32,
@@ -22,74 +36,589 @@ pub struct CellSize {
     pub padding: u32,
 }
 
+impl CellSize {
+    /// Distance between the centres of neighbouring cells.
+    pub fn span(&self) -> f32 {
+        (self.size + self.padding) as f32
+    }
+}
+
+impl Default for CellSize {
+    fn default() -> Self {
+        Self {
+            size: 60,
+            padding: 14,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EquationStatus {
+    /// Some blanks of the equation are still empty.
+    Incomplete,
+    Solved,
+    /// Every blank is filled, but the equation does not hold.
+    Wrong,
+}
+
+/// An equation whose status changed with an edit of the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusChange {
+    pub equation: usize,
+    pub from: EquationStatus,
+    pub to: EquationStatus,
+}
+
 #[derive(Debug, Resource)]
 pub struct Board {
+    /// The puzzle, with blanks as `Symbol::Unknown`.
     pub grid: Vec<Vec<Symbol>>,
     pub cell_size: CellSize,
-    pub covered_tiles: HashMap<Coordinates, Entity>,
+    pub cells: HashMap<Coordinates, Entity>,
+    pub equations: Vec<GridEquation>,
+    /// Symbols the player entered into blanks.
+    pub entries: HashMap<Coordinates, Symbol>,
+    /// Status of each of the `equations`.
+    pub statuses: Vec<EquationStatus>,
+    /// Number of actions on the blanks. Undoing and redoing them are not
+    /// moves of their own.
+    pub moves: u32,
+    /// Status changes not yet sent as events.
+    pub changes: Vec<StatusChange>,
+    pub history: History,
+    /// Number of hints the player asked for.
+    pub hints: u32,
+}
+
+impl Board {
+    pub fn symbol(&self, coordinates: Coordinates) -> Option<&Symbol> {
+        let x = usize::try_from(coordinates.x).ok()?;
+        let y = usize::try_from(coordinates.y).ok()?;
+        self.grid.get(x)?.get(y)
+    }
+
+    /// Centre of a cell in world space. Rows go down the screen, so vertical
+    /// equations read top to bottom.
+    pub fn cell_position(&self, coordinates: Coordinates) -> Vec2 {
+        cell_position(self.cell_size, coordinates)
+    }
+
+    /// Area covered by the cells within `bounds`, in world space.
+    pub fn world_rect(&self, bounds: Bounds) -> Rect {
+        let min = self.cell_position(Coordinates::new(bounds.min.0, bounds.min.1));
+        let max = self.cell_position(Coordinates::new(bounds.max.0, bounds.max.1));
+        Rect::from_corners(min, max).inflate(self.cell_size.span() / 2.0)
+    }
+
+    /// The cell nearest to a point in world space, if it is on the board.
+    pub fn coordinates_at(&self, position: Vec2) -> Option<Coordinates> {
+        let span = self.cell_size.span();
+        let coordinates = Coordinates::new(
+            (position.x / span + 0.5).floor() as i32,
+            (-position.y / span + 0.5).floor() as i32,
+        );
+        self.symbol(coordinates).map(|_| coordinates)
+    }
+
+    pub fn is_blank(&self, coordinates: Coordinates) -> bool {
+        matches!(self.symbol(coordinates), Some(Symbol::Unknown(_)))
+    }
+
+    /// The symbol that belongs in a blank.
+    pub fn solution(&self, coordinates: Coordinates) -> Option<&Symbol> {
+        match self.symbol(coordinates)? {
+            Symbol::Unknown(solution) => Some(solution),
+            _ => None,
+        }
+    }
+
+    /// The symbol shown in a cell, which for blanks is what the player
+    /// entered, if anything.
+    pub fn shown(&self, coordinates: Coordinates) -> Option<&Symbol> {
+        match self.symbol(coordinates)? {
+            Symbol::Unknown(_) => self.entries.get(&coordinates),
+            symbol => Some(symbol),
+        }
+    }
+
+    /// Fills a blank with `symbol`, or clears it, and checks every equation
+    /// through it. Returns false if the cell is not a blank.
+    pub fn enter(&mut self, coordinates: Coordinates, symbol: Option<Symbol>) -> bool {
+        if !self.is_blank(coordinates) {
+            return false;
+        }
+        if self.entries.get(&coordinates) == symbol.as_ref() {
+            return true;
+        }
+        match symbol {
+            Some(symbol) => self.entries.insert(coordinates, symbol),
+            None => self.entries.remove(&coordinates),
+        };
+
+        for i in 0..self.equations.len() {
+            let eq = &self.equations[i];
+            if !eq.contains_point((coordinates.x, coordinates.y)) {
+                continue;
+            }
+            let status = self.check(eq);
+            if status != self.statuses[i] {
+                self.changes.push(StatusChange {
+                    equation: i,
+                    from: self.statuses[i],
+                    to: status,
+                });
+            }
+            self.statuses[i] = status;
+        }
+        true
+    }
+
+    /// Fills or clears blanks as one action that can be undone. Returns false
+    /// if nothing changed.
+    pub fn edit(
+        &mut self,
+        kind: ActionKind,
+        edits: impl IntoIterator<Item = (Coordinates, Option<Symbol>)>,
+    ) -> bool {
+        let mut action = Action {
+            kind,
+            edits: Vec::new(),
+        };
+        for (cell, symbol) in edits {
+            let before = self.entries.get(&cell).cloned();
+            if before == symbol || !self.enter(cell, symbol.clone()) {
+                continue;
+            }
+            action.edits.push(Edit {
+                cell,
+                before,
+                after: symbol,
+            });
+        }
+        if action.edits.is_empty() {
+            return false;
+        }
+        self.history.record(action);
+        self.moves += 1;
+        true
+    }
+
+    /// Changes the symbol just entered into `cell`, as part of the same
+    /// action, like the digits of a number typed one by one.
+    pub fn amend(&mut self, cell: Coordinates, symbol: Option<Symbol>) -> bool {
+        let amends_last = self.history.undone.is_empty()
+            && self.history.done.last().is_some_and(|action| {
+                action.kind == ActionKind::Play
+                    && action.edits.len() == 1
+                    && action.edits[0].cell == cell
+            });
+        if !amends_last {
+            return self.edit(ActionKind::Play, [(cell, symbol)]);
+        }
+        if !self.enter(cell, symbol.clone()) {
+            return false;
+        }
+        if let Some(action) = self.history.done.last_mut() {
+            action.edits[0].after = symbol;
+        }
+        true
+    }
+
+    pub fn undo(&mut self) -> bool {
+        let Some(action) = self.history.done.pop() else {
+            return false;
+        };
+        for edit in action.edits.iter().rev() {
+            self.enter(edit.cell, edit.before.clone());
+        }
+        self.history.undone.push(action);
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let Some(action) = self.history.undone.pop() else {
+            return false;
+        };
+        for edit in action.edits.iter() {
+            self.enter(edit.cell, edit.after.clone());
+        }
+        self.history.done.push(action);
+        true
+    }
+
+    /// Tiles left to fill the blanks with: the solutions of all blanks, less
+    /// the symbols already entered.
+    pub fn remaining_tiles(&self) -> Vec<Symbol> {
+        let mut tiles: Vec<Symbol> = self
+            .grid
+            .iter()
+            .flatten()
+            .filter_map(|symbol| match symbol {
+                Symbol::Unknown(solution) => Some(*solution.clone()),
+                _ => None,
+            })
+            .collect();
+        for entry in self.entries.values() {
+            if let Some(i) = tiles.iter().position(|tile| tile == entry) {
+                tiles.remove(i);
+            }
+        }
+        tiles
+    }
+
+    /// Checks an equation as the player filled it in.
+    pub fn check(&self, eq: &GridEquation) -> EquationStatus {
+        let mut symbols = Vec::new();
+        for i in 0..eq.len() {
+            let (x, y) = eq.pos_at(i as i32);
+            match self.shown(Coordinates::new(x, y)) {
+                Some(symbol) => symbols.push(symbol.clone()),
+                None => return EquationStatus::Incomplete,
+            }
+        }
+
+        let lhs_len = eq.eq.lhs().len();
+        let filled = Equation::new(symbols[..lhs_len].to_vec(), symbols[lhs_len + 1..].to_vec());
+        if filled.holds() {
+            EquationStatus::Solved
+        } else {
+            EquationStatus::Wrong
+        }
+    }
+
+    pub fn is_solved(&self) -> bool {
+        self.statuses
+            .iter()
+            .all(|status| *status == EquationStatus::Solved)
+    }
+
+    /// The look of a tile. Wrong equations are marked on every cell. Blanks
+    /// turn green once every equation through them is solved.
+    pub fn tile_kind(&self, coordinates: Coordinates) -> Option<TileKind> {
+        let statuses: Vec<EquationStatus> = self
+            .equations
+            .iter()
+            .zip(self.statuses.iter())
+            .filter(|(eq, _)| eq.contains_point((coordinates.x, coordinates.y)))
+            .map(|(_, status)| *status)
+            .collect();
+
+        let kind = match self.symbol(coordinates)? {
+            Symbol::Empty => return None,
+            _ if statuses.contains(&EquationStatus::Wrong) => TileKind::Wrong,
+            Symbol::Unknown(_)
+                if statuses
+                    .iter()
+                    .all(|status| *status == EquationStatus::Solved) =>
+            {
+                TileKind::Solved
+            }
+            Symbol::Unknown(_) => TileKind::Blank,
+            Symbol::Number(_) => TileKind::Number,
+            Symbol::Equals => TileKind::Equals,
+            _ => TileKind::Operator,
+        };
+        Some(kind)
+    }
 }
 
+fn cell_position(cell_size: CellSize, coordinates: Coordinates) -> Vec2 {
+    let span = cell_size.span();
+    Vec2::new(coordinates.x as f32 * span, -(coordinates.y as f32) * span)
+}
+
+/// The 3D tile of a cell.
 #[derive(Component)]
-pub struct Cover;
+pub struct Tile;
+
+/// The symbol written on the face of a tile.
+#[derive(Component)]
+pub struct Glyph;
+
+// Size of the glyphs relative to the tiles.
+const GLYPH_SCALE: f32 = 0.9;
+// Keeps the glyphs just in front of the faces of the tiles.
+const GLYPH_LIFT: f32 = 0.5;
+
+/// A square tile of `size` facing the camera, with its face at z = 0, its
+/// edges bevelled by `bevel` at 45 degrees and its sides `depth` deep. The
+/// textures are projected onto it from the front, so they cover the face.
+fn tile_mesh(size: f32, depth: f32, bevel: f32) -> Mesh {
+    let outer = size / 2.0;
+    let inner = outer - bevel;
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    let mut quad = |corners: [Vec3; 4], normal: Vec3| {
+        let first = positions.len() as u32;
+        positions.extend(corners);
+        normals.extend([normal; 4]);
+        indices.extend([0, 1, 2, 0, 2, 3].map(|i| first + i));
+    };
+
+    quad(
+        [
+            Vec3::new(-inner, -inner, 0.0),
+            Vec3::new(inner, -inner, 0.0),
+            Vec3::new(inner, inner, 0.0),
+            Vec3::new(-inner, inner, 0.0),
+        ],
+        Vec3::Z,
+    );
+    for outward in [Vec2::X, Vec2::Y, Vec2::NEG_X, Vec2::NEG_Y] {
+        // Along the edge, counterclockwise seen from the front.
+        let along = outward.perp();
+        let corner = |distance: f32, side: f32, z: f32| {
+            (outward * distance + along * side * distance).extend(z)
+        };
+        quad(
+            [
+                corner(outer, -1.0, -bevel),
+                corner(outer, 1.0, -bevel),
+                corner(inner, 1.0, 0.0),
+                corner(inner, -1.0, 0.0),
+            ],
+            // The bevel is as deep as it is wide.
+            (outward.extend(0.0) + Vec3::Z).normalize(),
+        );
+        quad(
+            [
+                corner(outer, -1.0, -depth),
+                corner(outer, 1.0, -depth),
+                corner(outer, 1.0, -bevel),
+                corner(outer, -1.0, -bevel),
+            ],
+            outward.extend(0.0),
+        );
+    }
+
+    let uvs: Vec<Vec2> = positions
+        .iter()
+        .map(|position| Vec2::new(position.x / size + 0.5, 0.5 - position.y / size))
+        .collect();
+    let mut mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+    .with_inserted_indices(Indices::U32(indices));
+    // The normal map of the tile material needs tangents.
+    if let Err(err) = mesh.generate_tangents() {
+        warn!("could not generate tangents of the tile mesh: {}", err);
+    }
+    mesh
+}
 
 pub fn setup_board(
     mut commands: Commands,
-    mut materials: ResMut<Assets<ColorMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
+    textures: Res<TextureManager>,
+    mut glyphs: ResMut<GlyphAtlas>,
     equations: Res<equation::GridEquations>,
+    hidden: Res<HiddenCells>,
+    cells: Query<Entity, With<Coordinates>>,
 ) {
     info!("setup board called!");
-    let grid_size = 20;
-    let cell_size = CellSize {
-        size: 60,
-        padding: 14,
-    };
+    // Clear the board of the previous puzzle, if any.
+    for cell in cells.iter() {
+        commands.entity(cell).despawn();
+    }
+
+    let (width, height) = equations
+        .bounds()
+        .map(|bounds| (bounds.width(), bounds.height()))
+        .unwrap_or((0, 0));
+    let cell_size = CellSize::default();
 
-    let mut grid = vec![vec![Symbol::Empty; grid_size]; grid_size];
+    // The layout is normalized, so every cell lies within 0..width, 0..height.
+    let mut grid = vec![vec![Symbol::Empty; height]; width];
     for eq in equations.iter() {
-        let symbols = eq.symbols();
-        let pos = eq.start_pos;
-        let dir = eq.direction.clone();
-        for (i, symbol) in symbols.iter().enumerate() {
-            let (x, y) = match dir {
-                equation::Direction::Horizontal => (pos.0 + i, pos.1),
-                equation::Direction::Vertical => (pos.0, pos.1 + i),
+        for ((x, y), symbol) in eq.cells() {
+            grid[x as usize][y as usize] = if hidden.contains(&(x, y)) {
+                Symbol::Unknown(Box::new(symbol))
+            } else {
+                symbol
             };
-            grid[x as usize][y as usize] = symbol.clone();
         }
     }
 
-    for x in 0..grid_size {
-        for y in 0..grid_size {
-            let symbol = &grid[x as usize][y as usize];
-            let x_pos = (cell_size.size + cell_size.padding) as f32 * x as f32;
-            let y_pos = (cell_size.size + cell_size.padding) as f32 * y as f32;
+    let tile_mesh = meshes.add(tile_mesh(cell_size.size as f32, TILE_DEPTH, TILE_BEVEL));
+    let mut board_cells = HashMap::new();
+    for (x, column) in grid.iter().enumerate() {
+        for (y, symbol) in column.iter().enumerate() {
+            let text = match symbol {
+                Symbol::Empty => continue,
+                Symbol::Unknown(_) => String::new(),
+                symbol => symbol.to_string(),
+            };
+            let position = cell_position(cell_size, Coordinates::new(x as i32, y as i32));
 
-            commands
+            let cell = commands
                 .spawn((
-                    Name::new(format!("({}, {}) symbol '{}'", x, y, symbol.to_string())),
+                    Name::new(format!("({}, {}) symbol '{}'", x, y, symbol)),
                     Coordinates::new(x as i32, y as i32),
-                    Text2d::new(symbol.to_string()),
-                    TextShadow::default(),
-                    Transform::from_xyz(x_pos, y_pos, 0.0),
+                    Transform::from_translation(position.extend(0.0)),
+                    Visibility::default(),
+                    StateScoped(InGame),
                 ))
                 .with_children(|child_builder| {
+                    // The material follows the status of the equations, see
+                    // `update_cells`.
                     child_builder.spawn((
                         Name::new(format!("Cell ({}, {})", x, y)),
-                        Mesh2d(meshes.add(Rectangle {
-                            half_size: Vec2::new(30.0, 30.0),
-                        })),
-                        MeshMaterial2d(materials.add(Color::srgb(0.9, 0.8, 0.6))),
-                        Transform::from_xyz(0.0, 0.0, 1.0),
-                        Cover,
+                        Mesh3d(tile_mesh.clone()),
+                        MeshMaterial3d(textures.material(TileKind::Blank)),
+                        Tile,
                     ));
-                });
+                    let glyph = glyphs.text_mesh(&text, &mut meshes);
+                    child_builder.spawn((
+                        Mesh3d(glyph.clone().unwrap_or_default()),
+                        MeshMaterial3d(glyphs.material.clone()),
+                        Transform::from_xyz(0.0, 0.0, GLYPH_LIFT)
+                            .with_scale(Vec3::splat(cell_size.size as f32 * GLYPH_SCALE)),
+                        if glyph.is_some() {
+                            Visibility::Inherited
+                        } else {
+                            Visibility::Hidden
+                        },
+                        Glyph,
+                    ));
+                })
+                .id();
+            board_cells.insert(Coordinates::new(x as i32, y as i32), cell);
         }
     }
 
-    commands.insert_resource(Board {
+    let mut board = Board {
         grid,
         cell_size,
-        covered_tiles: HashMap::new(),
-    });
+        cells: board_cells,
+        equations: equations.to_vec(),
+        entries: HashMap::new(),
+        statuses: Vec::new(),
+        moves: 0,
+        changes: Vec::new(),
+        history: History::default(),
+        hints: 0,
+    };
+    board.statuses = board.equations.iter().map(|eq| board.check(eq)).collect();
+    commands.insert_resource(board);
+}
+
+/// Gives the tiles the material of the status of their equations, or of the
+/// hint shown.
+pub fn update_cells(
+    board: Res<Board>,
+    hint: Option<Res<ActiveHint>>,
+    cells: Query<(&Coordinates, &Children)>,
+    mut tiles: Query<&mut MeshMaterial3d<StandardMaterial>, With<Tile>>,
+    textures: Res<TextureManager>,
+) {
+    for (coordinates, children) in cells.iter() {
+        let hinted = hint
+            .as_ref()
+            .is_some_and(|hint| hint.highlights(&board, *coordinates));
+        let kind = if hinted {
+            Some(TileKind::Hint)
+        } else {
+            board.tile_kind(*coordinates)
+        };
+        let Some(material) = kind.map(|kind| textures.material(kind)) else {
+            continue;
+        };
+        let mut children = tiles.iter_many_mut(children);
+        while let Some(mut tile) = children.fetch_next() {
+            if tile.0 != material {
+                tile.0 = material.clone();
+            }
+        }
+    }
+}
+
+/// Writes the entries of the blanks on their tiles.
+pub fn update_glyphs(
+    board: Res<Board>,
+    cells: Query<(&Coordinates, &Children)>,
+    mut glyphs: Query<(&mut Mesh3d, &mut Visibility), With<Glyph>>,
+    mut atlas: ResMut<GlyphAtlas>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for (coordinates, children) in cells.iter() {
+        if !board.is_blank(*coordinates) {
+            continue;
+        }
+        let shown = board
+            .shown(*coordinates)
+            .map_or(String::new(), |symbol| symbol.to_string());
+        let mesh = atlas.text_mesh(&shown, &mut meshes);
+
+        let mut children = glyphs.iter_many_mut(children);
+        while let Some((mut glyph, mut visibility)) = children.fetch_next() {
+            match &mesh {
+                Some(mesh) if glyph.0 != *mesh => glyph.0 = mesh.clone(),
+                _ => {}
+            }
+            visibility.set_if_neq(if mesh.is_some() {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::equation::test_util::{equation, number};
+    use crate::equation::{Direction, Operator};
+
+    // `2 + 3 = 5` along the top row, with blanks for the 2 and the 3.
+    fn board() -> Board {
+        let eq = equation(2, Operator::Add, 3, 5, (0, 0), Direction::Horizontal);
+        let grid = eq
+            .cells()
+            .into_iter()
+            .map(|((x, _), symbol)| match x {
+                0 | 2 => vec![Symbol::Unknown(Box::new(symbol))],
+                _ => vec![symbol],
+            })
+            .collect();
+        Board {
+            grid,
+            cell_size: CellSize::default(),
+            cells: HashMap::new(),
+            equations: vec![eq],
+            entries: HashMap::new(),
+            statuses: vec![EquationStatus::Incomplete],
+            moves: 0,
+            changes: Vec::new(),
+            history: History::default(),
+            hints: 0,
+        }
+    }
+
+    #[test]
+    fn counts_actions_as_moves() {
+        let mut board = board();
+        let (first, second) = (Coordinates::new(0, 0), Coordinates::new(2, 0));
+        assert!(board.edit(ActionKind::Play, [(first, Some(number(1)))]));
+        // Typing the second digit of a number is part of the same move.
+        assert!(board.amend(first, Some(number(2))));
+        assert_eq!(board.moves, 1);
+
+        assert!(!board.edit(ActionKind::Play, [(first, Some(number(2)))]));
+        assert!(board.edit(ActionKind::Play, [(second, Some(number(3)))]));
+        assert_eq!(board.statuses[0], EquationStatus::Solved);
+        assert_eq!(board.moves, 2);
+
+        assert!(board.undo());
+        assert!(board.redo());
+        assert!(board.undo());
+        assert_eq!(board.statuses[0], EquationStatus::Incomplete);
+        assert_eq!(board.entries.get(&first), Some(&number(2)));
+        assert_eq!(board.moves, 2);
+    }
 }
@@ -0,0 +1,237 @@
+use bevy::input::gestures::PinchGesture;
+use bevy::input::mouse::{AccumulatedMouseScroll, MouseScrollUnit};
+use bevy::math::StableInterpolate;
+use bevy::prelude::*;
+
+use crate::board::Board;
+use crate::equation::{Bounds, GridEquation, Symbol};
+use crate::player_input::{BoardCamera, CursorPicker, Selection};
+use crate::rack::TileDrag;
+use crate::ui::UiFocus;
+
+// Scale change per line scrolled with the mouse wheel.
+const ZOOM_PER_LINE: f32 = 0.15;
+const PIXELS_PER_LINE: f32 = 20.0;
+// How fast the camera catches up with its target, per second.
+const SMOOTHING: f32 = 12.0;
+// Space left around whatever is framed.
+const FRAME_MARGIN: f32 = 1.15;
+// How far the view can zoom out, relative to the scale that fits the board.
+const MAX_ZOOM_OUT: f32 = 2.0;
+// Fewest cells across the view when zoomed in all the way.
+const MIN_CELLS_IN_VIEW: f32 = 3.0;
+
+/// The camera the 3D tiles are rendered with. It is a child of the board
+/// camera, so it follows it, and takes its zoom from it.
+#[derive(Component)]
+pub struct SceneCamera;
+
+/// Where the board camera is headed. The camera eases towards its target
+/// every frame, so zooming and framing are smooth.
+#[derive(Component, Debug)]
+pub struct CameraController {
+    pub translation: Vec2,
+    pub scale: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// The centre of the view is kept within this area.
+    pub bounds: Rect,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self {
+            translation: Vec2::ZERO,
+            scale: 1.0,
+            min_scale: 0.1,
+            max_scale: 10.0,
+            bounds: Rect::new(-1000.0, -1000.0, 1000.0, 1000.0),
+        }
+    }
+}
+
+impl CameraController {
+    /// Zooms by `factor`, keeping `anchor` at the same spot in the view.
+    pub fn zoom(&mut self, factor: f32, anchor: Vec2) {
+        let scale = (self.scale * factor).clamp(self.min_scale, self.max_scale);
+        self.translation = anchor + (self.translation - anchor) * scale / self.scale;
+        self.scale = scale;
+        self.clamp();
+    }
+
+    pub fn pan(&mut self, offset: Vec2) {
+        self.translation += offset;
+        self.clamp();
+    }
+
+    /// Centres `rect` in a view of `viewport` size and zooms to fit it.
+    pub fn frame(&mut self, rect: Rect, viewport: Vec2) {
+        self.translation = rect.center();
+        self.scale = fit_scale(rect, viewport).clamp(self.min_scale, self.max_scale);
+        self.clamp();
+    }
+
+    /// Takes the zoom and pan limits from the size of the board.
+    pub fn limit_to(&mut self, board: &Board, viewport: Vec2) {
+        let Some(bounds) = Bounds::of(&board.equations) else {
+            return;
+        };
+        let rect = board.world_rect(bounds);
+        let fit = fit_scale(rect, viewport);
+        self.bounds = rect;
+        self.max_scale = fit * MAX_ZOOM_OUT;
+        self.min_scale =
+            (board.cell_size.span() * MIN_CELLS_IN_VIEW / viewport.min_element()).min(fit);
+    }
+
+    fn clamp(&mut self) {
+        self.translation = self.translation.clamp(self.bounds.min, self.bounds.max);
+    }
+}
+
+fn fit_scale(rect: Rect, viewport: Vec2) -> f32 {
+    (rect.size() / viewport).max_element() * FRAME_MARGIN
+}
+
+/// Frames the whole board, and sets the limits of the camera from its size.
+pub fn fit_to_board(
+    board: Res<Board>,
+    window: Single<&Window>,
+    controller: Single<&mut CameraController>,
+) {
+    let mut controller = controller.into_inner();
+    controller.limit_to(&board, window.size());
+    if let Some(bounds) = Bounds::of(&board.equations) {
+        controller.frame(board.world_rect(bounds), window.size());
+    }
+}
+
+/// Zooms with the mouse wheel and pinch gestures, around the cursor.
+pub fn zoom_camera(
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+    mut pinch: EventReader<PinchGesture>,
+    picker: CursorPicker,
+    controller: Single<&mut CameraController>,
+    ui_focus: Res<UiFocus>,
+) {
+    let mut controller = controller.into_inner();
+    let pinched: f32 = pinch.read().map(|gesture| gesture.0).sum();
+    if ui_focus.pointer {
+        return;
+    }
+
+    let lines = match mouse_scroll.unit {
+        MouseScrollUnit::Line => mouse_scroll.delta.y,
+        MouseScrollUnit::Pixel => mouse_scroll.delta.y / PIXELS_PER_LINE,
+    };
+    let factor = (-lines * ZOOM_PER_LINE).exp() / (1.0 + pinched);
+    if factor != 1.0 {
+        let anchor = picker.world_position().unwrap_or(controller.translation);
+        controller.zoom(factor, anchor);
+    }
+}
+
+/// Pans by dragging the background with the left button, or anything with
+/// the middle button. Dragging a tile or clicking a cell takes precedence.
+pub fn pan_camera(
+    mouse_button_input: Res<ButtonInput<MouseButton>>,
+    picker: CursorPicker,
+    controller: Single<&mut CameraController>,
+    board: Option<Res<Board>>,
+    drag: Res<TileDrag>,
+    ui_focus: Res<UiFocus>,
+    mut pan_from: Local<Option<Vec2>>,
+) {
+    let mut controller = controller.into_inner();
+    let Some(board) = board else {
+        return;
+    };
+
+    let on_cell = picker
+        .cell(&board)
+        .and_then(|cell| board.symbol(cell))
+        .is_some_and(|symbol| *symbol != Symbol::Empty);
+    let starts_pan = mouse_button_input.just_pressed(MouseButton::Middle)
+        || (mouse_button_input.just_pressed(MouseButton::Left) && drag.0.is_none() && !on_cell);
+    if starts_pan && !ui_focus.pointer {
+        *pan_from = picker.cursor_position();
+    }
+    if !mouse_button_input.any_pressed([MouseButton::Left, MouseButton::Middle]) {
+        *pan_from = None;
+    }
+
+    let (Some(from), Some(to)) = (*pan_from, picker.cursor_position()) else {
+        return;
+    };
+    // The view moves with the cursor, and rows go down the window.
+    let delta = (to - from) * controller.scale;
+    controller.pan(Vec2::new(-delta.x, delta.y));
+    *pan_from = Some(to);
+}
+
+/// Frames the board with F and the selected equation with Z.
+pub fn camera_shortcuts(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    window: Single<&Window>,
+    controller: Single<&mut CameraController>,
+    board: Option<Res<Board>>,
+    selection: Res<Selection>,
+    ui_focus: Res<UiFocus>,
+) {
+    let mut controller = controller.into_inner();
+    let Some(board) = board else {
+        return;
+    };
+    if ui_focus.keyboard {
+        return;
+    }
+
+    let board_bounds = Bounds::of(&board.equations);
+    if let Some(bounds) = board_bounds.filter(|_| keyboard_input.just_pressed(KeyCode::KeyF)) {
+        controller.frame(board.world_rect(bounds), window.size());
+    }
+    // Ctrl+Z is undo.
+    let ctrl = keyboard_input.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ]);
+    if keyboard_input.just_pressed(KeyCode::KeyZ) && !ctrl {
+        let selected: Option<&GridEquation> = selection.cell.and_then(|cell| {
+            board
+                .equations
+                .iter()
+                .find(|eq| eq.contains_point((cell.x, cell.y)))
+        });
+        if let Some(bounds) = selected.and_then(|eq| Bounds::of([eq])) {
+            controller.frame(board.world_rect(bounds), window.size());
+        }
+    }
+}
+
+/// Eases the board camera towards the target of its `CameraController`.
+pub fn move_camera(
+    time: Res<Time>,
+    camera: Single<(&mut Transform, &mut Projection, &CameraController), With<BoardCamera>>,
+    mut scene_cameras: Query<&mut Projection, (With<SceneCamera>, Without<BoardCamera>)>,
+) {
+    let (mut transform, mut projection, controller) = camera.into_inner();
+    let delta = time.delta_secs();
+
+    let mut translation = transform.translation.truncate();
+    translation.smooth_nudge(&controller.translation, SMOOTHING, delta);
+    transform.translation = translation.extend(transform.translation.z);
+
+    let Projection::Orthographic(orthographic) = projection.as_mut() else {
+        return;
+    };
+    orthographic
+        .scale
+        .smooth_nudge(&controller.scale, SMOOTHING, delta);
+    for mut scene_projection in scene_cameras.iter_mut() {
+        if let Projection::Orthographic(scene) = scene_projection.as_mut() {
+            scene.scale = orthographic.scale;
+        }
+    }
+}
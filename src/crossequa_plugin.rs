@@ -2,29 +2,35 @@ use bevy::{
     DefaultPlugins,
     app::{App, Plugin, Startup},
     ecs::system::{Commands, Res},
-    math::Vec3,
     prelude::*,
     transform::components::Transform,
     utils::default,
 };
 
-use crate::texture_manager::TextureManager;
+use bevy::input::common_conditions::input_toggle_active;
+use bevy::time::common_conditions::on_timer;
+use bevy_egui::EguiContextPass;
 
 use crate::board;
+use crate::camera;
+use crate::difficulty;
 use crate::equation;
+use crate::game_state::{self, GameState, InGame};
+use crate::glyph_atlas::GlyphAtlas;
+use crate::hint;
+use crate::history;
+use crate::lighting;
 use crate::player_input;
+use crate::progress;
+use crate::rack;
+use crate::save;
+use crate::texture_manager::{self, TextureManager};
+use crate::tile_animation;
+use crate::ui;
 
 pub struct CrossequaPlugin;
 
 
-use std::f32::consts::TAU;
-
-// Define a component to designate a rotation speed to an entity.
-#[derive(Component)]
-struct Rotatable {
-    speed: f32,
-}
-
 // fn startup(mut commands: Commands) {
 //     commands.spawn((
 //         Camera2d::default(),
@@ -42,80 +48,203 @@ struct Rotatable {
 //     ));
 // }
 
-// This system will rotate any entity in the scene with a Rotatable component around its y-axis.
-fn rotate_cube(mut cubes: Query<(&mut Transform, &Rotatable)>, timer: Res<Time>) {
-    for (mut transform, cube) in &mut cubes {
-        // The speed is first multiplied by TAU which is a full rotation (360deg) in radians,
-        // and then multiplied by delta_secs which is the time that passed last frame.
-        // In other words. Speed is equal to the amount of rotations per second.
-        transform.rotate_y(cube.speed * TAU * timer.delta_secs());
-        transform.rotate_z(cube.speed * TAU * timer.delta_secs());
-    }
-}
-
-fn startup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    asset_server: Res<AssetServer>,
-) {
-    let cube = bevy::math::primitives::Cuboid::new(0.5, 0.5, 0.5);
-    let cube_mesh = meshes.add(cube);
-
-    // TODO: ...
-    // let a = asset_server.as_ref();
-
-    let texture_manager = TextureManager::new(asset_server.as_ref());
-
-    let material = texture_manager.get_tile_material();
-
-    // add entities to the world
-    for y in 0..=1 {
-        for x in 0..=1 {
-            bevy::log::info!("cube {} {}", x, y);
-            // sphere
-            commands.spawn((
-                Mesh3d(cube_mesh.clone()),
-                MeshMaterial3d(materials.add(material.clone())),
-                Transform::from_xyz((x + 1) as f32, (y + 1)as f32, 0.0),
-                Rotatable { speed: 0.3 },
+fn startup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    // Draws the interface on top of the 3D tiles. Both cameras are HDR, so
+    // they share the same image and the tiles can bloom.
+    commands
+        .spawn((
+            Camera2d,
+            Camera {
+                order: 1,
+                hdr: true,
+                clear_color: ClearColorConfig::None,
+                ..default()
+            },
+            player_input::BoardCamera,
+            camera::CameraController::default(),
+        ))
+        .with_children(|board_camera| {
+            // Looks at the board the same way, from in front of the tiles.
+            board_camera.spawn((
+                Camera3d::default(),
+                Camera {
+                    hdr: true,
+                    ..default()
+                },
+                Transform::from_xyz(0.0, 0.0, 500.0),
+                Projection::from(OrthographicProjection::default_3d()),
+                EnvironmentMapLight {
+                    diffuse_map: asset_server
+                        .load("environment_maps/pisa_diffuse_rgb9e5_zstd.ktx2"),
+                    specular_map: asset_server
+                        .load("environment_maps/pisa_specular_rgb9e5_zstd.ktx2"),
+                    intensity: 900.0,
+                    ..default()
+                },
+                camera::SceneCamera,
             ));
-        }
-    }
-
-    commands.spawn((
-        DirectionalLight {
-            illuminance: 1_500.,
-            ..default()
-        },
-        Transform::from_xyz(50.0, 50.0, 50.0).looking_at(Vec3::ZERO, Vec3::Y),
-    ));
-
-    // camera
-    commands.spawn((
-        Camera3d::default(),
-        Transform::from_xyz(0.0, 0.0, 800.0).looking_at(Vec3::default(), Vec3::new(0.1,0.8, 0.1)),
-        Projection::from(OrthographicProjection {
-            scale: 0.01,
-
-            ..OrthographicProjection::default_3d()
-        }),
-        EnvironmentMapLight {
-            diffuse_map: asset_server.load("environment_maps/pisa_diffuse_rgb9e5_zstd.ktx2"),
-            specular_map: asset_server.load("environment_maps/pisa_specular_rgb9e5_zstd.ktx2"),
-            intensity: 900.0,
-            ..default()
-        },
-    ));
+        });
 }
 
 impl Plugin for CrossequaPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(DefaultPlugins)
+            .init_resource::<difficulty::DifficultyProfile>()
+            .init_resource::<equation::LayoutConstraints>()
+            .init_resource::<equation::PuzzleSeed>()
+            .init_resource::<rack::TileDrag>()
+            .init_resource::<player_input::Selection>()
+            .init_resource::<ui::UiFocus>()
+            .init_resource::<TextureManager>()
+            .init_resource::<GlyphAtlas>()
+            .init_resource::<tile_animation::AnimationSettings>()
+            .init_resource::<lighting::SceneLighting>()
+            .add_event::<progress::EquationSolved>()
+            .add_event::<progress::EquationBroken>()
+            .add_event::<progress::PuzzleCompleted>()
+            .init_state::<GameState>()
+            .add_computed_state::<InGame>()
+            .enable_state_scoped_entities::<GameState>()
+            .enable_state_scoped_entities::<InGame>()
             .add_systems(
                 Startup,
-                (equation::generate_equations, startup, board::setup_board).chain(),
+                (startup, lighting::setup_lighting, save::load_game),
+            )
+            .add_systems(OnEnter(GameState::MainMenu), game_state::spawn_main_menu)
+            .add_systems(
+                OnEnter(GameState::Generating),
+                (
+                    difficulty::generate_puzzle.run_if(not(resource_exists::<save::ResumedGame>)),
+                    (
+                        board::setup_board,
+                        camera::fit_to_board,
+                        progress::start_clock,
+                        save::resume_game.run_if(resource_exists::<save::ResumedGame>),
+                        game_state::finish_generating,
+                    )
+                        .chain()
+                        .run_if(resource_exists::<equation::GridEquations>),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                OnEnter(InGame),
+                (
+                    rack::setup_rack,
+                    player_input::setup_cursor,
+                    history::setup_history_buttons,
+                    hint::setup_hints,
+                    rack::update_rack,
+                )
+                    .chain(),
             )
-            .add_systems(Update, (rotate_cube, player_input::handle_mouse_click));
+            .add_systems(OnExit(InGame), game_state::leave_game)
+            .add_systems(
+                OnEnter(GameState::Paused),
+                (game_state::spawn_pause_menu, save::save_game),
+            )
+            .add_systems(
+                OnEnter(GameState::Results),
+                (progress::show_completion_screen, save::delete_save),
+            )
+            .add_systems(
+                Update,
+                (
+                    game_state::menu_buttons,
+                    game_state::restart_on_new_puzzle,
+                    game_state::toggle_pause,
+                    texture_manager::update_materials,
+                    (lighting::apply_lights, lighting::apply_post_processing)
+                        .before(texture_manager::update_materials)
+                        .run_if(resource_changed::<lighting::SceneLighting>),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    rack::start_drag,
+                    rack::move_ghost,
+                    rack::end_drag,
+                    player_input::handle_mouse_click,
+                    player_input::handle_keyboard,
+                    history::undo_redo,
+                    hint::give_hint,
+                    hint::clear_stale_hint.run_if(resource_exists::<hint::ActiveHint>),
+                    player_input::update_cursor,
+                    progress::send_board_events,
+                    game_state::finish_puzzle,
+                    progress::tick_clock,
+                    board::update_cells.run_if(
+                        resource_changed::<board::Board>
+                            .or(resource_exists_and_changed::<hint::ActiveHint>)
+                            .or(resource_removed::<hint::ActiveHint>),
+                    ),
+                    board::update_glyphs.run_if(resource_changed::<board::Board>),
+                    hint::update_hint_text.run_if(
+                        resource_exists_and_changed::<hint::ActiveHint>
+                            .or(resource_removed::<hint::ActiveHint>),
+                    ),
+                    history::update_history_buttons.run_if(resource_changed::<board::Board>),
+                    rack::update_rack.run_if(
+                        resource_changed::<board::Board>
+                            .or(resource_changed::<rack::TileDrag>),
+                    ),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                Update,
+                (
+                    (
+                        tile_animation::start_reveals.run_if(resource_changed::<board::Board>),
+                        tile_animation::start_feedback,
+                    )
+                        .after(progress::send_board_events)
+                        .run_if(in_state(GameState::Playing)),
+                    (tile_animation::animate_motion, tile_animation::animate_glow)
+                        .after(board::update_cells)
+                        .run_if(in_state(InGame)),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    (
+                        camera::zoom_camera,
+                        camera::pan_camera.after(rack::start_drag),
+                        camera::camera_shortcuts,
+                    )
+                        .run_if(in_state(GameState::Playing).or(in_state(GameState::Results))),
+                    camera::move_camera,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                save::save_game
+                    .after(progress::send_board_events)
+                    .run_if(in_state(GameState::Playing).and(
+                        resource_changed::<board::Board>.or(on_timer(save::AUTOSAVE_INTERVAL)),
+                    )),
+            )
+            // Keeps the time spent when the window is closed.
+            .add_systems(
+                Last,
+                save::save_game.run_if(
+                    on_event::<AppExit>
+                        .and(in_state(GameState::Playing).or(in_state(GameState::Paused))),
+                ),
+            )
+            .add_systems(
+                EguiContextPass,
+                (
+                    ui::settings_window,
+                    ui::lighting_window.run_if(input_toggle_active(false, KeyCode::F2)),
+                    ui::seed_window,
+                )
+                    .chain(),
+            );
     }
 }
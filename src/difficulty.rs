@@ -0,0 +1,358 @@
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+
+use crate::equation::{
+    EquationGenerator, GenerationMode, GridEquation, GridEquations, LayoutConstraints,
+    NegativePolicy, NumberDomain, Operator, PuzzleSeed, UnaryOperator,
+};
+use crate::game_state::GameState;
+use crate::placement;
+use crate::solver::{self, HiddenCells, Solver, Technique};
+
+// Number of puzzles generated while looking for one with the target rating.
+const MAX_RATING_ATTEMPTS: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Medium,
+    Hard,
+    Expert,
+}
+
+impl Difficulty {
+    pub fn all() -> [Difficulty; 4] {
+        [
+            Difficulty::Easy,
+            Difficulty::Medium,
+            Difficulty::Hard,
+            Difficulty::Expert,
+        ]
+    }
+
+    /// Scores of the puzzles rated with this difficulty.
+    pub fn scores(&self) -> Range<u32> {
+        match self {
+            Difficulty::Easy => 0..2,
+            Difficulty::Medium => 2..4,
+            Difficulty::Hard => 4..8,
+            Difficulty::Expert => 8..u32::MAX,
+        }
+    }
+
+    pub fn of_score(score: u32) -> Difficulty {
+        Difficulty::all()
+            .into_iter()
+            .find(|difficulty| difficulty.scores().contains(&score))
+            .unwrap_or(Difficulty::Expert)
+    }
+
+    // How far `score` is from the scores of this difficulty.
+    fn distance(&self, score: u32) -> u32 {
+        let scores = self.scores();
+        if score < scores.start {
+            scores.start - score
+        } else if score >= scores.end {
+            score - scores.end + 1
+        } else {
+            0
+        }
+    }
+}
+
+impl Technique {
+    /// How much a deduction with this technique adds to the rating. Filling
+    /// in the last cell of an equation takes no thought, so it is free.
+    pub fn cost(&self) -> u32 {
+        match self {
+            Technique::LastCell => 0,
+            Technique::Equation => 1,
+            Technique::Crossing => 3,
+            Technique::Guess => 6,
+        }
+    }
+}
+
+/// Everything that makes a puzzle harder or easier to solve. The generator
+/// retries until the rating of the puzzle matches `target`.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DifficultyProfile {
+    pub target: Difficulty,
+    pub mode: GenerationMode,
+    pub negative_policy: NegativePolicy,
+    pub number_domain: NumberDomain,
+    /// Largest integer operand, operands are drawn from `1..=max_operand`.
+    pub max_operand: i64,
+    pub operators: Vec<Operator>,
+    pub unary_operators: Vec<UnaryOperator>,
+    pub lhs_operations: u32,
+    pub rhs_operations: u32,
+    pub num_equations: usize,
+    /// Between 0 and 1. Denser layouts prefer equations that cross several
+    /// others.
+    pub crossing_density: f64,
+    pub hidden_cells: usize,
+}
+
+impl DifficultyProfile {
+    pub fn preset(target: Difficulty) -> Self {
+        match target {
+            Difficulty::Easy => Self {
+                target,
+                mode: GenerationMode::Precedence,
+                negative_policy: NegativePolicy::Forbid,
+                number_domain: NumberDomain::Integers,
+                max_operand: 9,
+                operators: vec![Operator::Add, Operator::Subtract],
+                unary_operators: Vec::new(),
+                lhs_operations: 1,
+                rhs_operations: 0,
+                num_equations: 6,
+                crossing_density: 0.0,
+                hidden_cells: 5,
+            },
+            Difficulty::Medium => Self {
+                target,
+                mode: GenerationMode::Precedence,
+                negative_policy: NegativePolicy::Forbid,
+                number_domain: NumberDomain::Integers,
+                max_operand: 9,
+                operators: Operator::basic(),
+                unary_operators: Vec::new(),
+                lhs_operations: 1,
+                rhs_operations: 0,
+                num_equations: 8,
+                crossing_density: 0.3,
+                hidden_cells: 8,
+            },
+            Difficulty::Hard => Self {
+                target,
+                mode: GenerationMode::Chained,
+                negative_policy: NegativePolicy::Forbid,
+                number_domain: NumberDomain::Fractions,
+                max_operand: 12,
+                operators: [Operator::basic(), vec![Operator::Power]].concat(),
+                unary_operators: vec![UnaryOperator::Square],
+                lhs_operations: 2,
+                rhs_operations: 0,
+                num_equations: 10,
+                crossing_density: 0.6,
+                hidden_cells: 12,
+            },
+            Difficulty::Expert => Self {
+                target,
+                mode: GenerationMode::Chained,
+                negative_policy: NegativePolicy::Allow,
+                number_domain: NumberDomain::Mixed,
+                max_operand: 15,
+                operators: [
+                    Operator::basic(),
+                    vec![Operator::Power, Operator::Root, Operator::Log],
+                ]
+                .concat(),
+                unary_operators: vec![
+                    UnaryOperator::Factorial,
+                    UnaryOperator::Square,
+                    UnaryOperator::Abs,
+                ],
+                lhs_operations: 2,
+                rhs_operations: 1,
+                num_equations: 12,
+                crossing_density: 1.0,
+                hidden_cells: 18,
+            },
+        }
+    }
+
+    pub fn generator(&self) -> EquationGenerator {
+        EquationGenerator {
+            mode: self.mode,
+            negative_policy: self.negative_policy,
+            number_domain: self.number_domain,
+            operators: self.operators.clone(),
+            max_operand: self.max_operand,
+            unary_operators: self.unary_operators.clone(),
+            lhs_operations: self.lhs_operations,
+            rhs_operations: self.rhs_operations,
+        }
+    }
+}
+
+impl Default for DifficultyProfile {
+    fn default() -> Self {
+        Self::preset(Difficulty::default())
+    }
+}
+
+/// Rating of the current puzzle.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct PuzzleRating {
+    pub score: u32,
+    pub difficulty: Difficulty,
+}
+
+/// Rates a puzzle by the deductions needed to solve it: every hidden cell
+/// costs more the harder the technique that determines it. More hidden cells
+/// only make a puzzle harder if they take more than filling in the blanks.
+pub fn rate(equations: &[GridEquation], hidden: &BTreeSet<(i32, i32)>) -> u32 {
+    Solver::new(equations, hidden)
+        .deductions()
+        .iter()
+        .map(|deduction| deduction.technique.cost())
+        .sum()
+}
+
+// A generated puzzle with its rating.
+struct Candidate {
+    equations: Vec<GridEquation>,
+    hidden: BTreeSet<(i32, i32)>,
+    score: u32,
+}
+
+/// Generates a puzzle from the `PuzzleSeed`, retrying until its rating
+/// matches the target of the `DifficultyProfile`. Keeps the closest puzzle if
+/// none does. Goes back to the main menu if no equation fits the
+/// `LayoutConstraints` at all.
+pub fn generate_puzzle(
+    mut commands: Commands,
+    profile: Res<DifficultyProfile>,
+    constraints: Res<LayoutConstraints>,
+    seed: Res<PuzzleSeed>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    info!(
+        "generating {:?} puzzle with seed {}",
+        profile.target, **seed
+    );
+    let mut rng = StdRng::seed_from_u64(**seed);
+    let generator = profile.generator();
+
+    let mut best: Option<Candidate> = None;
+    for _ in 0..MAX_RATING_ATTEMPTS {
+        let Some(layout) = placement::generate_layout(
+            profile.num_equations,
+            &generator,
+            &constraints,
+            profile.crossing_density,
+            &mut rng,
+        ) else {
+            continue;
+        };
+        let hidden = solver::pick_hidden_cells(&layout, profile.hidden_cells, &mut rng);
+        let score = rate(&layout, &hidden);
+
+        let distance = profile.target.distance(score);
+        if best
+            .as_ref()
+            .is_none_or(|best| distance < profile.target.distance(best.score))
+        {
+            best = Some(Candidate {
+                equations: layout,
+                hidden,
+                score,
+            });
+        }
+        if distance == 0 {
+            break;
+        }
+    }
+    let Some(Candidate {
+        equations: grid_equations,
+        hidden,
+        score,
+    }) = best
+    else {
+        error!("no equation fits within {:?}", *constraints);
+        commands.remove_resource::<GridEquations>();
+        next_state.set(GameState::MainMenu);
+        return;
+    };
+
+    for grid_equation in grid_equations.iter() {
+        debug!("equation: {}", grid_equation);
+        if let Err(err) = grid_equation.eq.evaluate() {
+            error!("equation {} does not evaluate: {}", grid_equation, err);
+        } else if !grid_equation.eq.holds() {
+            error!("equation {} does not hold", grid_equation);
+        }
+    }
+    let difficulty = Difficulty::of_score(score);
+    if difficulty != profile.target {
+        warn!(
+            "could only generate a {:?} puzzle (score {}) instead of {:?}",
+            difficulty, score, profile.target
+        );
+    }
+
+    commands.insert_resource(GridEquations(grid_equations));
+    commands.insert_resource(HiddenCells(hidden));
+    commands.insert_resource(PuzzleRating { score, difficulty });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::equation::Direction;
+    use crate::equation::test_util::equation;
+
+    // The equations, with their positions, and hidden cells generated from
+    // `seed`.
+    fn generate(target: Difficulty, seed: u64) -> (Vec<String>, BTreeSet<(i32, i32)>) {
+        let mut world = World::new();
+        world.insert_resource(DifficultyProfile::preset(target));
+        world.init_resource::<LayoutConstraints>();
+        world.insert_resource(PuzzleSeed(seed));
+        world.init_resource::<NextState<GameState>>();
+        world.run_system_once(generate_puzzle).unwrap();
+
+        let equations = world
+            .resource::<GridEquations>()
+            .iter()
+            .map(|eq| eq.to_string())
+            .collect();
+        let hidden = world.resource::<HiddenCells>().0.clone();
+        (equations, hidden)
+    }
+
+    #[test]
+    fn rates_techniques_not_hidden_cells() {
+        // `2 + 3 = 5` and `2 + 4 = 6` one above the other.
+        let equations = [
+            equation(2, Operator::Add, 3, 5, (0, 0), Direction::Horizontal),
+            equation(2, Operator::Add, 4, 6, (0, 2), Direction::Horizontal),
+        ];
+
+        // Each blank is the last of its equation.
+        let last_cells = BTreeSet::from([(0, 0), (4, 2)]);
+        assert_eq!(rate(&equations, &last_cells), 0);
+        assert_eq!(Difficulty::of_score(0), Difficulty::Easy);
+
+        // The 2 and the 5 share an equation, so one of them has to be
+        // deduced before the other is the last blank.
+        let shared = BTreeSet::from([(0, 0), (4, 0)]);
+        assert_eq!(rate(&equations, &shared), Technique::Equation.cost());
+    }
+
+    #[test]
+    fn same_seed_gives_same_puzzle() {
+        for target in Difficulty::all() {
+            for seed in [1, 42] {
+                let puzzle = generate(target, seed);
+                assert!(!puzzle.0.is_empty());
+                assert_eq!(puzzle, generate(target, seed), "{target:?} seed {seed}");
+            }
+        }
+        assert_ne!(
+            generate(Difficulty::Medium, 1),
+            generate(Difficulty::Medium, 2)
+        );
+    }
+}
@@ -1,13 +1,33 @@
 use bevy::prelude::*;
 use rand::Rng;
-use rand::prelude::IndexedRandom;
+use rand::prelude::{IndexedRandom, SliceRandom};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::evaluator::{self, EvalError};
+use crate::number::{Number, Rational};
+
+// Generation is retried when an attempt is rejected, e.g. because its result
+// is negative or does not fit on a tile.
+const MAX_GENERATION_ATTEMPTS: usize = 100;
+// Largest denominator of a generated fraction.
+const MAX_DENOMINATOR: i64 = 12;
+// Powers, roots and logarithms are generated so their operands stay below this.
+const MAX_POWER: i64 = 1000;
+// Chance that an operand is decorated with one of the unary operators.
+const UNARY_OPERATOR_CHANCE: f64 = 0.3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Operator {
     Add,
     Subtract,
     Multiply,
     Divide,
+    /// `a ^ b`
+    Power,
+    /// `n √ x`, the n-th root of x
+    Root,
+    /// `b log x`, the logarithm of x to base b
+    Log,
 }
 
 impl std::fmt::Display for Operator {
@@ -17,24 +37,191 @@ impl std::fmt::Display for Operator {
             Operator::Subtract => "-",
             Operator::Multiply => "*",
             Operator::Divide => "/",
+            Operator::Power => "^",
+            Operator::Root => "√",
+            Operator::Log => "log",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl Operator {
+    pub fn all() -> [Operator; 7] {
+        [
+            Operator::Add,
+            Operator::Subtract,
+            Operator::Multiply,
+            Operator::Divide,
+            Operator::Power,
+            Operator::Root,
+            Operator::Log,
+        ]
+    }
+
+    pub fn basic() -> Vec<Operator> {
+        vec![
+            Operator::Add,
+            Operator::Subtract,
+            Operator::Multiply,
+            Operator::Divide,
+        ]
+    }
+
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Operator::Add | Operator::Subtract => 1,
+            Operator::Multiply | Operator::Divide => 2,
+            Operator::Power | Operator::Root | Operator::Log => 3,
+        }
+    }
+
+    /// `2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`; all other operators associate left.
+    pub fn is_right_associative(&self) -> bool {
+        self.precedence() == 3
+    }
+
+    /// Computes `a operator b` exactly. The result is written as a fraction
+    /// if either operand is one, otherwise as a decimal if either operand is
+    /// one, so `1 / 4` gives `1/4` and `0.5 / 2` gives `0.25`.
+    pub fn apply(&self, a: Number, b: Number) -> Result<Number, EvalError> {
+        let (x, y) = (a.value(), b.value());
+        let value = match self {
+            Operator::Add => x.checked_add(&y),
+            Operator::Subtract => x.checked_sub(&y),
+            Operator::Multiply => x.checked_mul(&y),
+            Operator::Divide => {
+                if y.is_zero() {
+                    return Err(EvalError::DivisionByZero);
+                }
+                x.checked_div(&y)
+            }
+            Operator::Power => {
+                let exponent = b.as_integer().ok_or(EvalError::NotExact)?;
+                if x.is_zero() && exponent < 0 {
+                    return Err(EvalError::DivisionByZero);
+                }
+                x.checked_pow(exponent)
+            }
+            Operator::Root => {
+                let degree = a
+                    .as_integer()
+                    .and_then(|n| u32::try_from(n).ok())
+                    .filter(|n| *n > 0)
+                    .ok_or(EvalError::Undefined)?;
+                if y.numerator() < 0 && degree % 2 == 0 {
+                    return Err(EvalError::Undefined);
+                }
+                Some(y.exact_root(degree).ok_or(EvalError::NotExact)?)
+            }
+            Operator::Log => {
+                return y
+                    .exact_log(&x)
+                    .map(Number::Integer)
+                    .ok_or(EvalError::NotExact);
+            }
+        };
+        let value = value.ok_or(EvalError::Overflow)?;
+
+        Ok(a.combine(&b, value))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum UnaryOperator {
+    /// `n !`
+    Factorial,
+    /// `n ²`
+    Square,
+    /// `abs n`
+    Abs,
+}
+
+impl std::fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            UnaryOperator::Factorial => "!",
+            UnaryOperator::Square => "²",
+            UnaryOperator::Abs => "abs",
         };
         write!(f, "{}", symbol)
     }
 }
 
+impl UnaryOperator {
+    pub fn all() -> [UnaryOperator; 3] {
+        [
+            UnaryOperator::Factorial,
+            UnaryOperator::Square,
+            UnaryOperator::Abs,
+        ]
+    }
+
+    /// Prefix operators are written in the cell before their operand, postfix
+    /// operators in the cell after it. Postfix operators bind tighter than
+    /// any binary operator.
+    pub fn is_prefix(&self) -> bool {
+        matches!(self, UnaryOperator::Abs)
+    }
+
+    pub fn apply(&self, a: Number) -> Result<Number, EvalError> {
+        match self {
+            UnaryOperator::Factorial => {
+                let n = a.as_integer().ok_or(EvalError::Undefined)?;
+                if !(0..=20).contains(&n) {
+                    return Err(if n < 0 {
+                        EvalError::Undefined
+                    } else {
+                        EvalError::Overflow
+                    });
+                }
+                Ok(Number::Integer((1..=n).product()))
+            }
+            UnaryOperator::Square => Operator::Multiply.apply(a, a),
+            UnaryOperator::Abs => {
+                if a.is_negative() {
+                    Operator::Subtract.apply(Number::Integer(0), a)
+                } else {
+                    Ok(a)
+                }
+            }
+        }
+    }
+
+    // Whether decorating `n` with this operator yields a small, exact result.
+    fn suits(&self, n: &Number) -> bool {
+        match self {
+            UnaryOperator::Factorial => n.as_integer().is_some_and(|n| (0..=6).contains(&n)),
+            UnaryOperator::Square => n.value().numerator().abs() <= 12,
+            UnaryOperator::Abs => n.is_negative(),
+        }
+    }
+}
+
 pub struct BinaryOperation {
-    pub num1: i32,
+    pub num1: Number,
     pub operator: Operator,
-    pub num2: i32,
-    pub result: i32,
+    pub num2: Number,
+    pub result: Number,
+}
+
+impl BinaryOperation {
+    /// Checks that `num1 operator num2 = result` holds exactly, so a
+    /// truncated division such as `7 / 2 = 3` is rejected.
+    pub fn verify(&self) -> bool {
+        self.operator.apply(self.num1, self.num2) == Ok(self.result)
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Symbol {
-    Number(i32),
+    Number(Number),
     Operator(Operator),
+    UnaryOperator(UnaryOperator),
+    OpenParen,
+    CloseParen,
     Equals,
-    Unknown, // TODO: Store the solution here
+    /// A blank cell of a puzzle, keeping the symbol that solves it.
+    Unknown(Box<Symbol>),
     Empty,
 }
 
@@ -42,24 +229,34 @@ impl std::fmt::Display for Symbol {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
             Symbol::Number(n) => format!("{n}"),
-            Symbol::Operator(Operator::Add) => "+".to_string(),
-            Symbol::Operator(Operator::Subtract) => "-".to_string(),
-            Symbol::Operator(Operator::Multiply) => "*".to_string(),
-            Symbol::Operator(Operator::Divide) => "/".to_string(),
+            Symbol::Operator(op) => op.to_string(),
+            Symbol::UnaryOperator(op) => op.to_string(),
+            Symbol::OpenParen => "(".to_string(),
+            Symbol::CloseParen => ")".to_string(),
             Symbol::Equals => "=".to_string(),
-            Symbol::Unknown => "x".to_string(),
+            Symbol::Unknown(_) => "x".to_string(),
             Symbol::Empty => " ".to_string(),
         };
         write!(f, "{s}")
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Direction {
     Horizontal,
     Vertical,
 }
 
+impl Direction {
+    pub fn perpendicular(&self) -> Direction {
+        match self {
+            Direction::Horizontal => Direction::Vertical,
+            Direction::Vertical => Direction::Horizontal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Equation {
     lhs: Vec<Symbol>,
     rhs: Vec<Symbol>,
@@ -70,11 +267,21 @@ impl Equation {
         Self { lhs, rhs }
     }
 
+    pub fn lhs(&self) -> &[Symbol] {
+        &self.lhs
+    }
+
+    pub fn rhs(&self) -> &[Symbol] {
+        &self.rhs
+    }
+
     pub fn nth(&self, n: usize) -> Option<&Symbol> {
         if n < self.lhs.len() {
             self.lhs.get(n)
+        } else if n == self.lhs.len() {
+            Some(&Symbol::Equals)
         } else {
-            self.rhs.get(n - self.lhs.len())
+            self.rhs.get(n - self.lhs.len() - 1)
         }
     }
 
@@ -88,54 +295,560 @@ impl Equation {
         full_equation.extend(self.rhs.clone());
         full_equation
     }
+
+    /// Evaluates both sides of the equation.
+    pub fn evaluate(&self) -> Result<(Number, Number), EvalError> {
+        Ok((
+            evaluator::evaluate(&self.lhs)?,
+            evaluator::evaluate(&self.rhs)?,
+        ))
+    }
+
+    /// Whether both sides have the same value, regardless of notation.
+    pub fn holds(&self) -> bool {
+        matches!(self.evaluate(), Ok((lhs, rhs)) if lhs.value() == rhs.value())
+    }
+}
+
+/// How the operations of a generated equation are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum GenerationMode {
+    /// Every operation is applied to the running result. Parentheses are
+    /// inserted wherever precedence would otherwise change the result, e.g.
+    /// `( 2 + 3 ) * 4 = 20`.
+    Chained,
+    /// Operations are written without parentheses and the result is computed
+    /// with the usual precedence, e.g. `2 + 3 * 4 = 14`.
+    #[default]
+    Precedence,
+}
+
+/// Whether generated equations may contain negative numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum NegativePolicy {
+    Allow,
+    /// Subtractions never go below zero, so neither do running results.
+    #[default]
+    Forbid,
+}
+
+impl NegativePolicy {
+    pub fn allows(&self, value: &Number) -> bool {
+        match self {
+            NegativePolicy::Allow => true,
+            NegativePolicy::Forbid => !value.is_negative(),
+        }
+    }
+}
+
+/// Which kinds of numbers generated equations may contain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum NumberDomain {
+    #[default]
+    Integers,
+    /// Integers and fractions such as `3/4`.
+    Fractions,
+    /// Integers and decimals such as `0.25`.
+    Decimals,
+    /// Integers, fractions and decimals, e.g. `1/2 + 0.25 = 3/4`.
+    Mixed,
+}
+
+impl NumberDomain {
+    pub fn contains(&self, n: &Number) -> bool {
+        matches!(
+            (self, n),
+            (_, Number::Integer(_))
+                | (NumberDomain::Mixed, _)
+                | (NumberDomain::Fractions, Number::Fraction(_))
+                | (NumberDomain::Decimals, Number::Decimal { .. })
+        )
+    }
+
+    fn random_operand(&self, max_integer: i64, rng: &mut impl Rng) -> Number {
+        let kinds: &[NumberDomain] = match self {
+            NumberDomain::Mixed => &[
+                NumberDomain::Integers,
+                NumberDomain::Fractions,
+                NumberDomain::Decimals,
+            ],
+            domain => &[NumberDomain::Integers, *domain],
+        };
+
+        match kinds.choose(rng).unwrap() {
+            NumberDomain::Fractions => {
+                let denominator = rng.random_range(2..=6);
+                let numerator = rng.random_range(1..denominator * 2);
+                Number::fraction(numerator, denominator).unwrap()
+            }
+            NumberDomain::Decimals => {
+                let places = rng.random_range(1..=2);
+                let units = rng.random_range(1..10_i64.pow(places));
+                // Drops trailing zeros, e.g. 0.40 becomes 0.4
+                Number::from_value(Rational::new(units, 10_i64.pow(places)).unwrap(), false)
+            }
+            _ => Number::Integer(rng.random_range(1..=max_integer)),
+        }
+    }
 }
 
-pub struct EquationGenerator {}
+#[derive(Resource, Debug, Clone)]
+pub struct EquationGenerator {
+    pub mode: GenerationMode,
+    pub negative_policy: NegativePolicy,
+    pub number_domain: NumberDomain,
+    pub operators: Vec<Operator>,
+    /// Largest integer operand, operands are drawn from `1..=max_operand`.
+    pub max_operand: i64,
+    /// Unary operators occasionally applied to operands. Empty by default.
+    pub unary_operators: Vec<UnaryOperator>,
+    /// Number of operations on the left-hand side.
+    pub lhs_operations: u32,
+    /// Number of operations on the right-hand side. With none, the right-hand
+    /// side is just the result, e.g. `3 * 4 = 12`, otherwise an expression of
+    /// the same value, e.g. `3 * 4 = 20 - 8`.
+    pub rhs_operations: u32,
+}
+
+impl Default for EquationGenerator {
+    fn default() -> Self {
+        Self {
+            mode: GenerationMode::default(),
+            negative_policy: NegativePolicy::default(),
+            number_domain: NumberDomain::default(),
+            operators: Operator::basic(),
+            max_operand: 9,
+            unary_operators: Vec::new(),
+            lhs_operations: 1,
+            rhs_operations: 0,
+        }
+    }
+}
 
 impl EquationGenerator {
-    pub fn generate_equation(running_result: Option<i32>, num_operations: u32) -> Equation {
-        let mut curr_equation: Vec<Symbol> = Vec::new();
+    /// Generates an equation with `lhs_operations` operations on the left and
+    /// `rhs_operations` on the right. If `running_result` is given, the
+    /// equation starts with it. Gives up if no attempt holds, e.g. because
+    /// the operators cannot continue from `running_result`.
+    pub fn generate_equation(
+        &self,
+        running_result: Option<Number>,
+        rng: &mut impl Rng,
+    ) -> Option<Equation> {
+        for _ in 0..MAX_GENERATION_ATTEMPTS {
+            let lhs = match self.mode {
+                GenerationMode::Chained => {
+                    self.generate_chained(running_result, self.lhs_operations, rng)
+                }
+                GenerationMode::Precedence => {
+                    self.generate_precedence(running_result, self.lhs_operations, rng)
+                }
+            };
+
+            let Some(lhs) = lhs else {
+                continue;
+            };
+            let Ok(result) = evaluator::evaluate(&lhs) else {
+                continue;
+            };
+            if !self.accepts(&result) {
+                continue;
+            }
+
+            let Some((rhs, _)) = self.generate_for_target(result, self.rhs_operations, rng) else {
+                continue;
+            };
+            let eq = Equation::new(lhs, rhs);
+            if eq.holds() {
+                return Some(eq);
+            }
+        }
+        None
+    }
+
+    // Whether `n` may appear in a generated equation.
+    fn accepts(&self, n: &Number) -> bool {
+        self.negative_policy.allows(n)
+            && self.number_domain.contains(n)
+            && n.is_simple(MAX_DENOMINATOR)
+    }
+
+    // Randomly picks a unary operator that suits `n`, if any.
+    fn pick_unary_operator(&self, n: &Number, rng: &mut impl Rng) -> Option<UnaryOperator> {
+        if !rng.random_bool(UNARY_OPERATOR_CHANCE) {
+            return None;
+        }
+
+        let suitable: Vec<&UnaryOperator> = self
+            .unary_operators
+            .iter()
+            .filter(|op| op.suits(n))
+            .collect();
+        let op = (*suitable.choose(rng)?).clone();
+        op.apply(*n)
+            .is_ok_and(|result| self.accepts(&result))
+            .then_some(op)
+    }
+
+    fn generate_chained(
+        &self,
+        running_result: Option<Number>,
+        num_operations: u32,
+        rng: &mut impl Rng,
+    ) -> Option<Vec<Symbol>> {
+        let mut lhs: Vec<Symbol> = Vec::new();
         let mut running_result = running_result;
+        // Lowest precedence of any operator outside of parentheses so far
+        let mut lowest_precedence: Option<u8> = None;
+
+        for i in 0..num_operations {
+            let op = self.generate_operation(running_result, rng)?;
+            let precedence = op.operator.precedence();
+            if i == 0 {
+                lhs.push(Symbol::Number(op.num1));
+            } else if lowest_precedence
+                .is_some_and(|lowest| lowest < precedence || op.operator.is_right_associative())
+            {
+                wrap_in_parens(&mut lhs, 0);
+                lowest_precedence = None;
+            }
+            lowest_precedence = Some(lowest_precedence.map_or(precedence, |p| p.min(precedence)));
+
+            lhs.push(Symbol::Operator(op.operator));
+            lhs.push(Symbol::Number(op.num2));
+            let mut result = op.result;
+
+            // Unary operators apply to everything so far, like any other step
+            // of the chain.
+            if let Some(unary) = self.pick_unary_operator(&result, rng) {
+                if lowest_precedence.is_some() {
+                    wrap_in_parens(&mut lhs, 0);
+                    lowest_precedence = None;
+                }
+                result = unary.apply(result).ok()?;
+                push_unary(&mut lhs, 0, unary);
+            }
+
+            running_result = Some(result);
+        }
+
+        Some(lhs)
+    }
+
+    fn generate_precedence(
+        &self,
+        first: Option<Number>,
+        num_operations: u32,
+        rng: &mut impl Rng,
+    ) -> Option<Vec<Symbol>> {
+        let mut lhs: Vec<Symbol> = Vec::new();
+        // Value of the term the next operator binds to, so that divisions,
+        // roots and logarithms stay exact under precedence. A term consisting
+        // of several operands is wrapped in parentheses before an operator
+        // binding tighter than its own operators is applied to it.
+        let mut term = first;
+        let mut term_start = 0;
+        let mut term_precedence: Option<u8> = None;
 
         for i in 0..num_operations {
-            let op = Self::generate_operation(running_result);
-            if i == 0 || running_result.is_none() {
-                curr_equation.push(Symbol::Number(op.num1));
+            let op = self.generate_operation(term, rng)?;
+            let precedence = op.operator.precedence();
+            if i == 0 {
+                lhs.push(Symbol::Number(op.num1));
+            }
+
+            match op.operator {
+                Operator::Add | Operator::Subtract => term_precedence = None,
+                _ => {
+                    if term_precedence
+                        .is_some_and(|p| p < precedence || op.operator.is_right_associative())
+                    {
+                        wrap_in_parens(&mut lhs, term_start);
+                    }
+                    term_precedence =
+                        Some(term_precedence.map_or(precedence, |p| p.min(precedence)));
+                }
+            }
+
+            lhs.push(Symbol::Operator(op.operator.clone()));
+            let operand_start = lhs.len();
+            let mut num2 = op.num2;
+            lhs.push(Symbol::Number(num2));
+            if term_precedence.is_none() {
+                term_start = operand_start;
             }
-            curr_equation.push(Symbol::Operator(op.operator));
-            curr_equation.push(Symbol::Number(op.num2));
 
-            running_result = Some(op.result);
+            // Unary operators only decorate the operand itself. The right
+            // operands of the other operators are picked to keep them exact,
+            // which decorating them would undo.
+            let unary = match op.operator {
+                Operator::Add | Operator::Subtract | Operator::Multiply => {
+                    self.pick_unary_operator(&num2, rng)
+                }
+                _ => None,
+            };
+            if let Some(unary) = unary {
+                num2 = unary.apply(num2).ok()?;
+                push_unary(&mut lhs, operand_start, unary);
+            }
+
+            term = match op.operator {
+                Operator::Add | Operator::Subtract => Some(num2),
+                _ => Some(op.operator.apply(op.num1, num2).ok()?),
+            };
+        }
+
+        Some(lhs)
+    }
+
+    // Generates an expression worth `target` backwards: the last operation is
+    // picked first and its left operand is generated the same way. Returns
+    // the symbols and the lowest precedence outside of parentheses.
+    fn generate_for_target(
+        &self,
+        target: Number,
+        num_operations: u32,
+        rng: &mut impl Rng,
+    ) -> Option<(Vec<Symbol>, Option<u8>)> {
+        if num_operations == 0 {
+            return self
+                .accepts(&target)
+                .then(|| (vec![Symbol::Number(target)], None));
+        }
+
+        let op = self.generate_reverse_operation(target, rng)?;
+        let (mut symbols, mut lowest_precedence) =
+            self.generate_for_target(op.num1, num_operations - 1, rng)?;
+        let precedence = op.operator.precedence();
+        if lowest_precedence
+            .is_some_and(|lowest| lowest < precedence || op.operator.is_right_associative())
+        {
+            wrap_in_parens(&mut symbols, 0);
+            lowest_precedence = None;
         }
 
-        Equation::new(curr_equation, vec![Symbol::Number(running_result.unwrap())])
+        symbols.push(Symbol::Operator(op.operator));
+        symbols.push(Symbol::Number(op.num2));
+        Some((
+            symbols,
+            Some(lowest_precedence.map_or(precedence, |p| p.min(precedence))),
+        ))
+    }
+
+    // An operation with the value of `result`, found by picking the right
+    // operand and solving for the left one.
+    fn generate_reverse_operation(
+        &self,
+        result: Number,
+        rng: &mut impl Rng,
+    ) -> Option<BinaryOperation> {
+        let mut operators = self.operators.clone();
+        operators.shuffle(rng);
+
+        operators.into_iter().find_map(|op| {
+            let (num1, num2) = self.reverse_operands(&op, &result, rng)?;
+            let exact = op
+                .apply(num1, num2)
+                .is_ok_and(|value| value.value() == result.value());
+            (exact && self.accepts(&num1) && self.accepts(&num2)).then_some(BinaryOperation {
+                num1,
+                operator: op,
+                num2,
+                result,
+            })
+        })
+    }
+
+    fn reverse_operands(
+        &self,
+        op: &Operator,
+        result: &Number,
+        rng: &mut impl Rng,
+    ) -> Option<(Number, Number)> {
+        let value = result.value();
+
+        match op {
+            Operator::Add | Operator::Subtract | Operator::Multiply | Operator::Divide => {
+                let b = match (op, self.number_domain) {
+                    (Operator::Multiply, NumberDomain::Integers) => {
+                        let r = result.as_integer()?;
+                        let divisors: Vec<i64> =
+                            (2..=self.max_operand).filter(|b| r % b == 0).collect();
+                        Number::Integer(*divisors.choose(rng)?)
+                    }
+                    (Operator::Divide, NumberDomain::Integers) => {
+                        Number::Integer(rng.random_range(2..=self.max_operand))
+                    }
+                    (_, NumberDomain::Integers) => {
+                        Number::Integer(rng.random_range(1..=self.max_operand))
+                    }
+                    (_, domain) => domain.random_operand(self.max_operand, rng),
+                };
+                let a = match op {
+                    Operator::Add => value.checked_sub(&b.value())?,
+                    Operator::Subtract => value.checked_add(&b.value())?,
+                    Operator::Multiply => value.checked_div(&b.value())?,
+                    _ => value.checked_mul(&b.value())?,
+                };
+                Some((result.combine(&b, a), b))
+            }
+            Operator::Power => {
+                let exponent = rng.random_range(2..=3);
+                let base = value.exact_root(exponent)?;
+                Some((
+                    Number::from_value(base, !result.is_decimal()),
+                    Number::Integer(exponent as i64),
+                ))
+            }
+            Operator::Root => {
+                let degree = rng.random_range(2..=3);
+                let radicand = value.checked_pow(degree)?;
+                Some((
+                    Number::Integer(degree),
+                    Number::from_value(radicand, !result.is_decimal()),
+                ))
+            }
+            Operator::Log => {
+                let exponent = result.as_integer().filter(|k| (1..=3).contains(k))?;
+                let base = rng.random_range(2..=10_i64);
+                Some((
+                    Number::Integer(base),
+                    Number::Integer(base.checked_pow(exponent as u32)?),
+                ))
+            }
+        }
+    }
+
+    fn generate_operation(
+        &self,
+        prev_a: Option<Number>,
+        rng: &mut impl Rng,
+    ) -> Option<BinaryOperation> {
+        let operation = match self.number_domain {
+            NumberDomain::Integers => {
+                let prev_a = match prev_a {
+                    Some(a) => Some(a.as_integer()?),
+                    None => None,
+                };
+                self.generate_integer_operation(prev_a, rng)?
+            }
+            _ => self.generate_rational_operation(prev_a, rng)?,
+        };
+
+        (operation.verify() && self.accepts(&operation.result)).then_some(operation)
     }
 
-    fn generate_operation(prev_a: Option<i32>) -> BinaryOperation {
-        let mut rng = rand::rng();
+    // Right operands `b` for which `a op b` is an integer. Divisions, roots
+    // and logarithms are generated backwards from products and powers, so
+    // they are always exact.
+    fn integer_operands(&self, op: &Operator, a: i64) -> Vec<i64> {
+        let powers_of = |base: i64, exponents: std::ops::RangeInclusive<u32>| -> Vec<i64> {
+            exponents
+                .filter_map(|e| base.checked_pow(e))
+                .filter(|p| p.abs() < MAX_POWER)
+                .collect()
+        };
 
-        let a = prev_a.unwrap_or_else(|| rng.random_range(1..10));
-        let b = rng.random_range(1..10);
-        let mut operators = vec![Operator::Add, Operator::Subtract, Operator::Multiply];
-        if b != 0 {
-            operators.push(Operator::Divide);
+        match op {
+            Operator::Add | Operator::Multiply => (1..=self.max_operand).collect(),
+            Operator::Subtract => match self.negative_policy {
+                NegativePolicy::Allow => (1..=self.max_operand).collect(),
+                NegativePolicy::Forbid => (1..=a.min(self.max_operand)).collect(),
+            },
+            Operator::Divide => (2..=self.max_operand).filter(|b| a % b == 0).collect(),
+            Operator::Power => (2..=3)
+                .filter(|e| {
+                    a.checked_pow(*e as u32)
+                        .is_some_and(|p| p.abs() < MAX_POWER)
+                })
+                .collect(),
+            Operator::Root if (2..=3).contains(&a) => (1..=self.max_operand)
+                .filter_map(|root: i64| root.checked_pow(a as u32))
+                .filter(|p| *p < MAX_POWER)
+                .collect(),
+            Operator::Log if (2..=10).contains(&a) => powers_of(a, 1..=3),
+            Operator::Root | Operator::Log => Vec::new(),
         }
+    }
 
-        let op = operators.choose(&mut rng).unwrap();
+    fn generate_integer_operation(
+        &self,
+        prev_a: Option<i64>,
+        rng: &mut impl Rng,
+    ) -> Option<BinaryOperation> {
+        let mut a = prev_a.unwrap_or_else(|| rng.random_range(1..=self.max_operand));
+        let candidates: Vec<(&Operator, Vec<i64>)> = self
+            .operators
+            .iter()
+            .map(|op| (op, self.integer_operands(op, a)))
+            .filter(|(op, operands)| {
+                !operands.is_empty() || (prev_a.is_none() && **op == Operator::Divide)
+            })
+            .collect();
+
+        let (op, operands) = candidates.choose(rng)?;
 
-        let (num1, num2, result) = match op {
-            Operator::Add => (a, b, a + b),
-            Operator::Subtract => (a, b, a - b),
-            Operator::Multiply => (a, b, a * b),
-            Operator::Divide => (a, b, a / b),
+        let b = match op {
+            // Without a given left operand, divisions are generated backwards
+            // from a product, so they are always exact.
+            Operator::Divide if prev_a.is_none() => {
+                let b = rng.random_range(2..=self.max_operand);
+                a = b * rng.random_range(1..=self.max_operand);
+                b
+            }
+            _ => *operands.choose(rng)?,
         };
 
-        return BinaryOperation {
+        let (num1, num2) = (Number::Integer(a), Number::Integer(b));
+        Some(BinaryOperation {
             num1,
-            operator: op.clone(),
+            operator: (*op).clone(),
             num2,
-            result,
+            result: op.apply(num1, num2).ok()?,
+        })
+    }
+
+    // Fractions and decimals are closed under the basic operations, so any
+    // operands work as long as the result stays readable. Powers, roots and
+    // logarithms fall back to the integer rules.
+    fn generate_rational_operation(
+        &self,
+        prev_a: Option<Number>,
+        rng: &mut impl Rng,
+    ) -> Option<BinaryOperation> {
+        let a = prev_a.unwrap_or_else(|| self.number_domain.random_operand(self.max_operand, rng));
+        let op = self.operators.choose(rng)?.clone();
+        let b = match op {
+            Operator::Add | Operator::Subtract | Operator::Multiply | Operator::Divide => {
+                self.number_domain.random_operand(self.max_operand, rng)
+            }
+            Operator::Power => Number::Integer(rng.random_range(2..=3)),
+            Operator::Root | Operator::Log => {
+                Number::Integer(*self.integer_operands(&op, a.as_integer()?).choose(rng)?)
+            }
         };
+
+        let result = op.apply(a, b).ok()?;
+        Some(BinaryOperation {
+            num1: a,
+            operator: op,
+            num2: b,
+            result,
+        })
+    }
+}
+
+fn wrap_in_parens(symbols: &mut Vec<Symbol>, start: usize) {
+    symbols.insert(start, Symbol::OpenParen);
+    symbols.push(Symbol::CloseParen);
+}
+
+// Applies `op` to the operand that starts at `start` and ends the expression.
+fn push_unary(symbols: &mut Vec<Symbol>, start: usize, op: UnaryOperator) {
+    if op.is_prefix() {
+        symbols.insert(start, Symbol::UnaryOperator(op));
+    } else {
+        symbols.push(Symbol::UnaryOperator(op));
     }
 }
 
@@ -144,14 +857,74 @@ impl EquationGenerator {
 #[derive(Resource, Deref)]
 pub struct GridEquations(pub Vec<GridEquation>);
 
+impl GridEquations {
+    pub fn bounds(&self) -> Option<Bounds> {
+        Bounds::of(self.iter())
+    }
+}
+
+/// Inclusive bounding box of a set of grid cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+}
+
+impl Bounds {
+    pub fn of<'a>(equations: impl IntoIterator<Item = &'a GridEquation>) -> Option<Bounds> {
+        let mut bounds: Option<Bounds> = None;
+        for eq in equations {
+            let eq_bounds = Bounds {
+                min: eq.start_pos,
+                max: eq.end_pos(),
+            };
+            bounds = Some(match bounds {
+                Some(bounds) => bounds.union(&eq_bounds),
+                None => eq_bounds,
+            });
+        }
+        bounds
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            min: (self.min.0.min(other.min.0), self.min.1.min(other.min.1)),
+            max: (self.max.0.max(other.max.0), self.max.1.max(other.max.1)),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        (self.max.0 - self.min.0 + 1) as usize
+    }
+
+    pub fn height(&self) -> usize {
+        (self.max.1 - self.min.1 + 1) as usize
+    }
+}
+
+/// Optional limits on the size of a generated layout.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct LayoutConstraints {
+    pub max_width: Option<usize>,
+    pub max_height: Option<usize>,
+}
+
+impl LayoutConstraints {
+    pub fn allows(&self, bounds: &Bounds) -> bool {
+        self.max_width.is_none_or(|w| bounds.width() <= w)
+            && self.max_height.is_none_or(|h| bounds.height() <= h)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GridEquation {
     pub eq: Equation,
-    pub start_pos: (usize, usize),
+    pub start_pos: (i32, i32),
     pub direction: Direction,
 }
 
 impl GridEquation {
-    pub fn new(eq: Equation, start_pos: (usize, usize), direction: Direction) -> Self {
+    pub fn new(eq: Equation, start_pos: (i32, i32), direction: Direction) -> Self {
         Self {
             eq,
             start_pos,
@@ -159,13 +932,8 @@ impl GridEquation {
         }
     }
 
-    pub fn end_pos(&self) -> (usize, usize) {
-        let (start_x, start_y) = self.start_pos;
-        let len = self.len();
-        match self.direction {
-            Direction::Horizontal => (start_x + len - 1, start_y),
-            Direction::Vertical => (start_x, start_y + len - 1),
-        }
+    pub fn end_pos(&self) -> (i32, i32) {
+        self.pos_at(self.len() as i32 - 1)
     }
 
     pub fn symbols(&self) -> Vec<Symbol> {
@@ -176,83 +944,76 @@ impl GridEquation {
         self.eq.len()
     }
 
-    pub fn get_symbol(&self, position: (usize, usize)) -> Option<&Symbol> {
-        let (x, y) = position;
+    /// Grid position of the symbol at `offset`. Offsets outside of the
+    /// equation are allowed and continue along its direction.
+    pub fn pos_at(&self, offset: i32) -> (i32, i32) {
         let (start_x, start_y) = self.start_pos;
-
-        if x < start_x || y < start_y {
-            return None;
+        match self.direction {
+            Direction::Horizontal => (start_x + offset, start_y),
+            Direction::Vertical => (start_x, start_y + offset),
         }
+    }
+
+    /// All cells covered by this equation, in reading order.
+    pub fn cells(&self) -> Vec<((i32, i32), Symbol)> {
+        self.symbols()
+            .into_iter()
+            .enumerate()
+            .map(|(i, symbol)| (self.pos_at(i as i32), symbol))
+            .collect()
+    }
 
-        let offset_x = x - start_x;
-        let offset_y = y - start_y;
+    pub fn translate(&mut self, dx: i32, dy: i32) {
+        self.start_pos = (self.start_pos.0 + dx, self.start_pos.1 + dy);
+    }
+
+    pub fn get_symbol(&self, position: (i32, i32)) -> Option<&Symbol> {
+        let (x, y) = position;
+        let (start_x, start_y) = self.start_pos;
 
         let offset = match self.direction {
-            Direction::Horizontal => {
-                if offset_y == 0 && offset_x < self.len() {
-                    Some(offset_x)
-                } else {
-                    None
-                }
-            }
-            Direction::Vertical => {
-                if offset_x == 0 && offset_y < self.len() {
-                    Some(offset_y)
-                } else {
-                    None
-                }
-            }
+            Direction::Horizontal if y == start_y => x - start_x,
+            Direction::Vertical if x == start_x => y - start_y,
+            _ => return None,
         };
 
-        if let Some(offset) = offset {
-            return self.eq.nth(offset);
+        if offset < 0 {
+            return None;
         }
 
-        None
-    }
-
-    pub fn pos_of_rand_number(&self) -> (usize, usize) {
-        let max_n = (self.eq.lhs.len() / 2 + 1) as u32;
-        let n = (rand::random::<u32>() % max_n) + 1;
-
-        self.pos_of_nth_number(n as usize).unwrap()
+        self.eq.nth(offset as usize)
     }
 
-    pub fn pos_of_nth_number(&self, n: usize) -> Option<(usize, usize)> {
-        let (start_x, start_y) = self.start_pos;
-
-        let mut counter_n = 0;
-        let mut counter = 0;
-        for sym in self.eq.lhs.clone() {
-            counter_n += 1;
-            if let Symbol::Number(_) = sym {
-                counter += 1;
-                if counter == n {
-                    break;
-                }
-            }
+    /// Position of a random number on either side of the equation, if it has
+    /// any.
+    pub fn pos_of_rand_number(&self, rng: &mut impl Rng) -> Option<(i32, i32)> {
+        let max_n = self
+            .symbols()
+            .iter()
+            .filter(|symbol| matches!(symbol, Symbol::Number(_)))
+            .count();
+        if max_n == 0 {
+            return None;
         }
+        let n = rng.random_range(1..=max_n);
 
-        if counter_n > self.eq.lhs.len() {
-            return None; // Out of bounds
-        }
+        self.pos_of_nth_number(n)
+    }
 
-        let (x, y) = match self.direction {
-            Direction::Horizontal => (start_x + counter_n - 1, start_y),
-            Direction::Vertical => (start_x, start_y + counter_n - 1),
-        };
+    /// Position of the `n`-th number, counting from one across both sides.
+    pub fn pos_of_nth_number(&self, n: usize) -> Option<(i32, i32)> {
+        let (index, _) = self
+            .symbols()
+            .iter()
+            .enumerate()
+            .filter(|(_, symbol)| matches!(symbol, Symbol::Number(_)))
+            .nth(n.checked_sub(1)?)?;
 
-        Some((x, y))
+        Some(self.pos_at(index as i32))
     }
 
-    pub fn contains_point(&self, point: (usize, usize)) -> bool {
-        let (start_x, start_y) = self.start_pos;
-        let (x, y) = point;
-
-        match self.direction {
-            Direction::Horizontal => y == start_y && x >= start_x && x <= start_x + self.len(),
-            Direction::Vertical => x == start_x && y >= start_y && y <= start_y + self.len(),
-        }
+    pub fn contains_point(&self, point: (i32, i32)) -> bool {
+        self.get_symbol(point).is_some()
     }
 }
 
@@ -268,33 +1029,42 @@ impl std::fmt::Display for GridEquation {
     }
 }
 
-pub fn generate_equations(mut commands: Commands) {
-    let mut position = (0, 0);
-    let mut dir = Direction::Horizontal;
-    let mut running_result = None;
-    let mut grid_equations = Vec::new();
+/// Seed of the current puzzle. The same seed and the same `DifficultyProfile`
+/// always produce the same `GridEquations`. Changing it generates a new board.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Deref)]
+pub struct PuzzleSeed(pub u64);
 
-    for _ in 0..8 {
-        let equation = EquationGenerator::generate_equation(running_result, 1);
-        let grid_equation = GridEquation::new(equation, position, dir.clone());
-
-        position = grid_equation.pos_of_rand_number();
-        dir = match &dir {
-            Direction::Horizontal => Direction::Vertical,
-            Direction::Vertical => Direction::Horizontal,
-        };
+impl Default for PuzzleSeed {
+    fn default() -> Self {
+        Self(rand::random())
+    }
+}
 
-        let next_symbol = grid_equation.get_symbol(position).unwrap();
-        match next_symbol {
-            &Symbol::Number(n) => {
-                running_result = Some(n);
-            }
-            _ => {}
-        }
+/// Equations for the tests of the modules that work with them.
+#[cfg(test)]
+pub mod test_util {
+    use super::*;
 
-        println!("equation: {}", grid_equation);
-        grid_equations.push(grid_equation);
+    pub fn number(n: i64) -> Symbol {
+        Symbol::Number(Number::Integer(n))
     }
 
-    commands.insert_resource(GridEquations(grid_equations));
+    /// `a op b = c` starting at `start`.
+    pub fn equation(
+        a: i64,
+        op: Operator,
+        b: i64,
+        c: i64,
+        start: (i32, i32),
+        direction: Direction,
+    ) -> GridEquation {
+        GridEquation::new(
+            Equation::new(
+                vec![number(a), Symbol::Operator(op), number(b)],
+                vec![number(c)],
+            ),
+            start,
+            direction,
+        )
+    }
 }
@@ -0,0 +1,225 @@
+use crate::equation::Symbol;
+use crate::number::Number;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    UnexpectedSymbol(Symbol),
+    UnexpectedEnd,
+    UnbalancedParentheses,
+    DivisionByZero,
+    /// The result has no exact rational value, e.g. `2 √ 2`.
+    NotExact,
+    /// The operation is not defined for its operands, e.g. `-3 !`.
+    Undefined,
+    Overflow,
+}
+
+impl std::fmt::Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::UnexpectedSymbol(symbol) => write!(f, "unexpected symbol '{symbol}'"),
+            EvalError::UnexpectedEnd => write!(f, "unexpected end of expression"),
+            EvalError::UnbalancedParentheses => write!(f, "unbalanced parentheses"),
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::NotExact => write!(f, "result is not exact"),
+            EvalError::Undefined => write!(f, "operation is undefined for its operands"),
+            EvalError::Overflow => write!(f, "arithmetic overflow"),
+        }
+    }
+}
+
+/// Evaluates an expression such as `2 + 3 * ( 4 - 1 )` with the usual
+/// operator precedence. Operators of the same precedence associate left,
+/// except for `^`, which associates right: `2 ^ 3 ^ 2` is `2 ^ 9`.
+pub fn evaluate(symbols: &[Symbol]) -> Result<Number, EvalError> {
+    let mut parser = Parser { symbols, pos: 0 };
+    let value = parser.expression(0)?;
+
+    match parser.peek() {
+        None => Ok(value),
+        Some(Symbol::CloseParen) => Err(EvalError::UnbalancedParentheses),
+        Some(symbol) => Err(EvalError::UnexpectedSymbol(symbol.clone())),
+    }
+}
+
+struct Parser<'a> {
+    symbols: &'a [Symbol],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Symbol> {
+        self.symbols.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&Symbol> {
+        let symbol = self.symbols.get(self.pos);
+        self.pos += 1;
+        symbol
+    }
+
+    // Precedence climbing: parses operands and every following operator that
+    // binds at least as tightly as `min_precedence`.
+    fn expression(&mut self, min_precedence: u8) -> Result<Number, EvalError> {
+        let mut lhs = self.operand()?;
+
+        while let Some(Symbol::Operator(op)) = self.peek() {
+            let op = op.clone();
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.pos += 1;
+
+            let next_precedence = if op.is_right_associative() {
+                op.precedence()
+            } else {
+                op.precedence() + 1
+            };
+            let rhs = self.expression(next_precedence)?;
+            lhs = op.apply(lhs, rhs)?;
+        }
+
+        Ok(lhs)
+    }
+
+    // A number or parenthesized expression together with its unary operators.
+    fn operand(&mut self) -> Result<Number, EvalError> {
+        let mut value = match self.next() {
+            Some(Symbol::Number(n)) => *n,
+            Some(Symbol::UnaryOperator(op)) if op.is_prefix() => {
+                let op = op.clone();
+                let value = self.operand()?;
+                return op.apply(value);
+            }
+            Some(Symbol::OpenParen) => {
+                let value = self.expression(0)?;
+                match self.next() {
+                    Some(Symbol::CloseParen) => value,
+                    _ => return Err(EvalError::UnbalancedParentheses),
+                }
+            }
+            Some(symbol) => return Err(EvalError::UnexpectedSymbol(symbol.clone())),
+            None => return Err(EvalError::UnexpectedEnd),
+        };
+
+        while let Some(Symbol::UnaryOperator(op)) = self.peek() {
+            if op.is_prefix() {
+                break;
+            }
+            value = op.apply(value)?;
+            self.pos += 1;
+        }
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::equation::{Operator, UnaryOperator};
+
+    fn n(n: i64) -> Symbol {
+        Symbol::Number(Number::Integer(n))
+    }
+
+    fn op(op: Operator) -> Symbol {
+        Symbol::Operator(op)
+    }
+
+    fn value(symbols: &[Symbol]) -> Result<i64, EvalError> {
+        evaluate(symbols).map(|n| n.as_integer().expect("an integer result"))
+    }
+
+    #[test]
+    fn respects_precedence() {
+        // 2 + 3 * 4
+        let symbols = [n(2), op(Operator::Add), n(3), op(Operator::Multiply), n(4)];
+        assert_eq!(value(&symbols), Ok(14));
+        // 2 * 3 + 4
+        let symbols = [n(2), op(Operator::Multiply), n(3), op(Operator::Add), n(4)];
+        assert_eq!(value(&symbols), Ok(10));
+        // ( 2 + 3 ) * 4
+        let symbols = [
+            Symbol::OpenParen,
+            n(2),
+            op(Operator::Add),
+            n(3),
+            Symbol::CloseParen,
+            op(Operator::Multiply),
+            n(4),
+        ];
+        assert_eq!(value(&symbols), Ok(20));
+        // 2 * 3 ^ 2
+        let symbols = [
+            n(2),
+            op(Operator::Multiply),
+            n(3),
+            op(Operator::Power),
+            n(2),
+        ];
+        assert_eq!(value(&symbols), Ok(18));
+    }
+
+    #[test]
+    fn associates_left_except_power() {
+        // 8 - 3 - 2
+        let symbols = [
+            n(8),
+            op(Operator::Subtract),
+            n(3),
+            op(Operator::Subtract),
+            n(2),
+        ];
+        assert_eq!(value(&symbols), Ok(3));
+        // 24 / 4 / 2
+        let symbols = [
+            n(24),
+            op(Operator::Divide),
+            n(4),
+            op(Operator::Divide),
+            n(2),
+        ];
+        assert_eq!(value(&symbols), Ok(3));
+        // 2 ^ 3 ^ 2 is 2 ^ 9, not 8 ^ 2
+        let symbols = [n(2), op(Operator::Power), n(3), op(Operator::Power), n(2)];
+        assert_eq!(value(&symbols), Ok(512));
+    }
+
+    #[test]
+    fn applies_unary_operators() {
+        // 3 ! + 2 ²
+        let symbols = [
+            n(3),
+            Symbol::UnaryOperator(UnaryOperator::Factorial),
+            op(Operator::Add),
+            n(2),
+            Symbol::UnaryOperator(UnaryOperator::Square),
+        ];
+        assert_eq!(value(&symbols), Ok(10));
+        // abs ( 2 - 5 )
+        let symbols = [
+            Symbol::UnaryOperator(UnaryOperator::Abs),
+            Symbol::OpenParen,
+            n(2),
+            op(Operator::Subtract),
+            n(5),
+            Symbol::CloseParen,
+        ];
+        assert_eq!(value(&symbols), Ok(3));
+    }
+
+    #[test]
+    fn reports_errors() {
+        let symbols = [n(1), op(Operator::Divide), n(0)];
+        assert_eq!(evaluate(&symbols), Err(EvalError::DivisionByZero));
+        let symbols = [Symbol::OpenParen, n(1), op(Operator::Add), n(2)];
+        assert_eq!(evaluate(&symbols), Err(EvalError::UnbalancedParentheses));
+        let symbols = [n(1), op(Operator::Add), n(2), Symbol::CloseParen];
+        assert_eq!(evaluate(&symbols), Err(EvalError::UnbalancedParentheses));
+        let symbols = [n(1), op(Operator::Add)];
+        assert_eq!(evaluate(&symbols), Err(EvalError::UnexpectedEnd));
+        let symbols = [n(2), op(Operator::Root), n(2)];
+        assert_eq!(evaluate(&symbols), Err(EvalError::NotExact));
+    }
+}
@@ -0,0 +1,201 @@
+use bevy::prelude::*;
+
+use crate::board::Board;
+use crate::difficulty::DifficultyProfile;
+use crate::equation::PuzzleSeed;
+use crate::hint::ActiveHint;
+use crate::player_input::Selection;
+use crate::progress::PuzzleCompleted;
+use crate::rack::TileDrag;
+
+const MENU_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+const MENU_TEXT_COLOR: Color = Color::srgb(0.98, 0.96, 0.9);
+const BUTTON_COLOR: Color = Color::srgb(0.95, 0.88, 0.7);
+const BUTTON_TEXT_COLOR: Color = Color::srgb(0.15, 0.1, 0.05);
+
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum GameState {
+    #[default]
+    MainMenu,
+    /// Generates the puzzle of the `PuzzleSeed` and sets up its board.
+    Generating,
+    Playing,
+    Paused,
+    /// The puzzle is completed.
+    Results,
+}
+
+/// While a board is set up: playing, paused or looking at the results. Board
+/// entities are scoped to it, so they are torn down when the game is left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InGame;
+
+impl ComputedStates for InGame {
+    type SourceStates = GameState;
+
+    fn compute(state: GameState) -> Option<Self> {
+        matches!(
+            state,
+            GameState::Playing | GameState::Paused | GameState::Results
+        )
+        .then_some(InGame)
+    }
+}
+
+/// What a button of a menu does.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuButton {
+    NewGame,
+    Resume,
+    MainMenu,
+}
+
+/// Spawns a screen-filling menu with a title, a line of text and buttons,
+/// scoped to `state`.
+pub fn spawn_menu<S: States>(
+    commands: &mut Commands,
+    state: S,
+    title: &str,
+    text: Option<String>,
+    buttons: &[(MenuButton, &str)],
+) {
+    commands
+        .spawn((
+            Name::new(format!("{} menu", title)),
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(12.0),
+                ..default()
+            },
+            BackgroundColor(MENU_COLOR),
+            GlobalZIndex(20),
+            StateScoped(state),
+        ))
+        .with_children(|menu| {
+            menu.spawn((
+                Text::new(title),
+                TextFont::from_font_size(48.0),
+                TextColor(MENU_TEXT_COLOR),
+            ));
+            if let Some(text) = text {
+                menu.spawn((Text::new(text), TextColor(MENU_TEXT_COLOR)));
+            }
+            for (button, label) in buttons {
+                menu.spawn((
+                    Button,
+                    Node {
+                        width: Val::Px(200.0),
+                        padding: UiRect::all(Val::Px(10.0)),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    BackgroundColor(BUTTON_COLOR),
+                    BorderRadius::all(Val::Px(6.0)),
+                    children![(Text::new(*label), TextColor(BUTTON_TEXT_COLOR))],
+                    *button,
+                ));
+            }
+        });
+}
+
+pub fn spawn_main_menu(mut commands: Commands) {
+    spawn_menu(
+        &mut commands,
+        GameState::MainMenu,
+        "Crossequa",
+        None,
+        &[(MenuButton::NewGame, "New game")],
+    );
+}
+
+pub fn spawn_pause_menu(mut commands: Commands) {
+    spawn_menu(
+        &mut commands,
+        GameState::Paused,
+        "Paused",
+        None,
+        &[
+            (MenuButton::Resume, "Resume"),
+            (MenuButton::NewGame, "New game"),
+            (MenuButton::MainMenu, "Main menu"),
+        ],
+    );
+}
+
+pub fn menu_buttons(
+    buttons: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut seed: ResMut<PuzzleSeed>,
+) {
+    for (interaction, button) in buttons.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            MenuButton::NewGame => *seed = PuzzleSeed(rand::random()),
+            MenuButton::Resume => next_state.set(GameState::Playing),
+            MenuButton::MainMenu => next_state.set(GameState::MainMenu),
+        }
+    }
+}
+
+/// Starts a new game whenever the seed changes, from the menus or the seed
+/// window. A new difficulty only restarts a game that is already running.
+pub fn restart_on_new_puzzle(
+    seed: Res<PuzzleSeed>,
+    profile: Res<DifficultyProfile>,
+    in_game: Option<Res<State<InGame>>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let new_seed = seed.is_changed() && !seed.is_added();
+    if new_seed || (in_game.is_some() && profile.is_changed()) {
+        next_state.set(GameState::Generating);
+    }
+}
+
+pub fn finish_generating(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Playing);
+}
+
+pub fn finish_puzzle(
+    mut completed: EventReader<PuzzleCompleted>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if completed.read().count() > 0 {
+        next_state.set(GameState::Results);
+    }
+}
+
+/// Escape pauses and resumes the game.
+pub fn toggle_pause(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    match state.get() {
+        GameState::Playing => next_state.set(GameState::Paused),
+        GameState::Paused => next_state.set(GameState::Playing),
+        _ => {}
+    }
+}
+
+/// Forgets the board of the game that was left. Its entities are despawned
+/// with the `InGame` state.
+pub fn leave_game(
+    mut commands: Commands,
+    mut selection: ResMut<Selection>,
+    mut drag: ResMut<TileDrag>,
+) {
+    commands.remove_resource::<Board>();
+    commands.remove_resource::<ActiveHint>();
+    *selection = Selection::default();
+    drag.0 = None;
+}
@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+
+use ab_glyph::{Font, FontRef, Point, PxScale, PxScaleFont, ScaleFont, point};
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+use crate::equation::{Operator, Symbol, UnaryOperator};
+
+// Characters of numbers besides the digits: sign, decimal point and fraction
+// slash.
+const NUMBER_CHARACTERS: &str = "-./";
+// Drawn as the bar of stacked fractions, as the default font has no fraction
+// slash.
+const FRACTION_BAR: char = '\u{2044}';
+// Shown for characters that are not in the atlas.
+const MISSING: char = '?';
+// Size of a superscript relative to a glyph.
+const SUPERSCRIPT_SCALE: f32 = 0.6;
+// Size of a glyph in the atlas, large enough to stay crisp zoomed in.
+const GLYPH_PIXELS: u32 = 96;
+const COLUMNS: u32 = 8;
+// Height of the font relative to a glyph.
+const FONT_SCALE: f32 = 0.85;
+const GLYPH_COLOR: Color = Color::srgb(0.15, 0.1, 0.05);
+
+/// The glyphs of all symbols, drawn once from the default font on startup.
+/// Tiles show their symbol with a mesh of quads that sample the atlas, see
+/// `text_mesh`.
+#[derive(Resource)]
+pub struct GlyphAtlas {
+    /// Draws glyphs in `GLYPH_COLOR`, unaffected by the lighting.
+    pub material: Handle<StandardMaterial>,
+    /// Rectangle of every character in the atlas, in UV coordinates.
+    uvs: HashMap<char, Rect>,
+    /// How far apart neighbouring glyphs are, relative to their size.
+    advance: f32,
+    meshes: HashMap<String, Handle<Mesh>>,
+}
+
+impl FromWorld for GlyphAtlas {
+    fn from_world(world: &mut World) -> Self {
+        let font = FontRef::try_from_slice(bevy::text::DEFAULT_FONT_DATA)
+            .expect("the default font can be read");
+        let font = font.as_scaled(PxScale::from(GLYPH_PIXELS as f32 * FONT_SCALE));
+        let glyph_size = GLYPH_PIXELS as f32;
+        // The font is monospaced.
+        let advance = font.h_advance(font.glyph_id('0'));
+
+        let characters = characters();
+        let rows = (characters.len() as u32).div_ceil(COLUMNS);
+        let size = UVec2::new(COLUMNS, rows) * GLYPH_PIXELS;
+        let mut coverage = vec![0.0_f32; (size.x * size.y) as usize];
+        let mut uvs = HashMap::new();
+        for (i, character) in characters.into_iter().enumerate() {
+            let origin = UVec2::new(i as u32 % COLUMNS, i as u32 / COLUMNS) * GLYPH_PIXELS;
+            let mut plot = |x: u32, y: u32, value: f32| {
+                if x < GLYPH_PIXELS && y < GLYPH_PIXELS {
+                    let index = ((origin.y + y) * size.x + origin.x + x) as usize;
+                    coverage[index] = coverage[index].max(value);
+                }
+            };
+
+            // Centred in its cell.
+            let baseline = (glyph_size + font.ascent() + font.descent()) / 2.0;
+            let missing = font.glyph_id(character).0 == 0;
+            // The default font has no radical sign and no superscripts.
+            if missing && character == '√' {
+                draw_radical(&mut plot);
+            } else if missing && character == '²' {
+                let scale = SUPERSCRIPT_SCALE;
+                let origin = point(
+                    (glyph_size - advance * scale) / 2.0,
+                    baseline - font.ascent() * (1.0 - scale),
+                );
+                draw_glyph(&font, '2', scale, origin, &mut plot);
+            } else if character == FRACTION_BAR {
+                draw_bar(&mut plot);
+            } else {
+                let origin = point((glyph_size - advance) / 2.0, baseline);
+                draw_glyph(&font, character, 1.0, origin, &mut plot);
+            }
+
+            let min = origin.as_vec2() / size.as_vec2();
+            let max = (origin + GLYPH_PIXELS).as_vec2() / size.as_vec2();
+            uvs.insert(character, Rect::from_corners(min, max));
+        }
+
+        // White, so the material colours the glyphs.
+        let data = coverage
+            .iter()
+            .flat_map(|value| [255, 255, 255, (value.clamp(0.0, 1.0) * 255.0) as u8])
+            .collect();
+        let image = Image::new(
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        let image = world.resource_mut::<Assets<Image>>().add(image);
+        let material = world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial {
+                base_color: GLYPH_COLOR,
+                base_color_texture: Some(image),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            });
+
+        GlyphAtlas {
+            material,
+            uvs,
+            advance: advance / glyph_size,
+            meshes: HashMap::new(),
+        }
+    }
+}
+
+impl GlyphAtlas {
+    /// A mesh that writes `text` in a row of glyphs, centred on the origin and
+    /// shrunk to fit the unit square. Fractions such as `1/2` are stacked over
+    /// a bar instead, so they do not read like a division. Meshes are kept for
+    /// reuse, as tiles show the same few symbols over and over. Works for
+    /// `Mesh3d` and `Mesh2d`.
+    pub fn text_mesh(&mut self, text: &str, meshes: &mut Assets<Mesh>) -> Option<Handle<Mesh>> {
+        if text.is_empty() {
+            return None;
+        }
+        if let Some(mesh) = self.meshes.get(text) {
+            return Some(mesh.clone());
+        }
+
+        let mut quads = Quads::default();
+        let fraction = text
+            .split_once('/')
+            .filter(|(numerator, denominator)| !numerator.is_empty() && !denominator.is_empty());
+        match fraction {
+            Some((numerator, denominator)) => {
+                let width = self.row_width(numerator).max(self.row_width(denominator));
+                let scale = width.recip().min(0.5);
+                self.push_row(&mut quads, numerator, Vec2::new(0.0, 0.25), scale);
+                self.push_row(&mut quads, denominator, Vec2::new(0.0, -0.25), scale);
+                quads.push(
+                    Vec2::ZERO,
+                    Vec2::new(width, 1.0) * scale,
+                    self.uvs[&FRACTION_BAR],
+                );
+            }
+            None => {
+                let scale = self.row_width(text).recip().min(1.0);
+                self.push_row(&mut quads, text, Vec2::ZERO, scale);
+            }
+        }
+
+        let mesh = meshes.add(quads.into_mesh());
+        self.meshes.insert(text.to_string(), mesh.clone());
+        Some(mesh)
+    }
+
+    // Width of a row of glyphs, relative to their size.
+    fn row_width(&self, text: &str) -> f32 {
+        (text.chars().count() as f32 - 1.0) * self.advance + 1.0
+    }
+
+    // Adds a row of glyphs of size `scale`, centred on `center`.
+    fn push_row(&self, quads: &mut Quads, text: &str, center: Vec2, scale: f32) {
+        let count = text.chars().count() as f32;
+        for (i, character) in text.chars().enumerate() {
+            let offset = (i as f32 - (count - 1.0) / 2.0) * self.advance;
+            let uv = self
+                .uvs
+                .get(&character)
+                .unwrap_or_else(|| &self.uvs[&MISSING]);
+            quads.push(
+                center + Vec2::new(offset * scale, 0.0),
+                Vec2::splat(scale),
+                *uv,
+            );
+        }
+    }
+}
+
+// Quads of a text mesh, each showing a part of the atlas.
+#[derive(Default)]
+struct Quads {
+    positions: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    indices: Vec<u32>,
+}
+
+impl Quads {
+    fn push(&mut self, center: Vec2, size: Vec2, uv: Rect) {
+        let first = self.positions.len() as u32;
+        self.positions.extend(
+            [
+                Vec2::new(-0.5, -0.5),
+                Vec2::new(0.5, -0.5),
+                Vec2::new(0.5, 0.5),
+                Vec2::new(-0.5, 0.5),
+            ]
+            .map(|corner| (center + corner * size).extend(0.0)),
+        );
+        // Rows of the image go down.
+        self.uvs.extend([
+            Vec2::new(uv.min.x, uv.max.y),
+            uv.max,
+            Vec2::new(uv.max.x, uv.min.y),
+            uv.min,
+        ]);
+        self.indices
+            .extend([0, 1, 2, 0, 2, 3].map(|corner| first + corner));
+    }
+
+    fn into_mesh(self) -> Mesh {
+        let normals = vec![Vec3::Z; self.positions.len()];
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs)
+        .with_inserted_indices(Indices::U32(self.indices))
+    }
+}
+
+/// Every character a symbol is written with: the digits, sign, decimal point
+/// and fraction bar of numbers, the operators, parentheses, `=` and `?`.
+fn characters() -> Vec<char> {
+    let symbols = Operator::all()
+        .map(Symbol::Operator)
+        .into_iter()
+        .chain(UnaryOperator::all().map(Symbol::UnaryOperator))
+        .chain([Symbol::OpenParen, Symbol::CloseParen, Symbol::Equals])
+        .map(|symbol| symbol.to_string());
+    let text: String = ('0'..='9')
+        .chain(NUMBER_CHARACTERS.chars())
+        .chain([FRACTION_BAR, MISSING])
+        .chain(symbols.flat_map(|text| text.chars().collect::<Vec<_>>()))
+        .collect();
+
+    let mut characters = Vec::new();
+    for character in text.chars() {
+        if !characters.contains(&character) {
+            characters.push(character);
+        }
+    }
+    characters
+}
+
+// Draws a character of the font, `scale` times its size, with its baseline
+// starting at `origin`.
+fn draw_glyph(
+    font: &PxScaleFont<&FontRef>,
+    character: char,
+    scale: f32,
+    origin: Point,
+    plot: &mut impl FnMut(u32, u32, f32),
+) {
+    let glyph = font
+        .glyph_id(character)
+        .with_scale_and_position(PxScale::from(font.scale().y * scale), origin);
+    if let Some(outline) = font.outline_glyph(glyph) {
+        let bounds = outline.px_bounds();
+        outline.draw(|x, y, value| {
+            let x = bounds.min.x as i32 + x as i32;
+            let y = bounds.min.y as i32 + y as i32;
+            if x >= 0 && y >= 0 {
+                plot(x as u32, y as u32, value);
+            }
+        });
+    }
+}
+
+// Strokes a radical sign into a glyph of the atlas, antialiased by the
+// distance of each pixel to the stroke.
+fn draw_radical(plot: &mut impl FnMut(u32, u32, f32)) {
+    let size = GLYPH_PIXELS as f32;
+    let points = [
+        Vec2::new(0.2, 0.55),
+        Vec2::new(0.3, 0.5),
+        Vec2::new(0.45, 0.78),
+        Vec2::new(0.62, 0.2),
+        Vec2::new(0.85, 0.2),
+    ]
+    .map(|point| point * size);
+    let half_width = size * 0.035;
+
+    for y in 0..GLYPH_PIXELS {
+        for x in 0..GLYPH_PIXELS {
+            let pixel = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let distance = points
+                .windows(2)
+                .map(|segment| distance_to_segment(pixel, segment[0], segment[1]))
+                .fold(f32::INFINITY, f32::min);
+            let value = (half_width - distance + 0.5).clamp(0.0, 1.0);
+            if value > 0.0 {
+                plot(x, y, value);
+            }
+        }
+    }
+}
+
+// A horizontal bar through the middle of a glyph, from edge to edge, so bars
+// stretched over several glyphs stay even.
+fn draw_bar(plot: &mut impl FnMut(u32, u32, f32)) {
+    let half_width = GLYPH_PIXELS as f32 * 0.04;
+    let middle = GLYPH_PIXELS as f32 / 2.0;
+    for y in 0..GLYPH_PIXELS {
+        let distance = (y as f32 + 0.5 - middle).abs();
+        let value = (half_width - distance + 0.5).clamp(0.0, 1.0);
+        if value > 0.0 {
+            for x in 0..GLYPH_PIXELS {
+                plot(x, y, value);
+            }
+        }
+    }
+}
+
+fn distance_to_segment(point: Vec2, start: Vec2, end: Vec2) -> f32 {
+    let along = end - start;
+    let t = ((point - start).dot(along) / along.length_squared()).clamp(0.0, 1.0);
+    point.distance(start + along * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The atlas and the image of its glyphs.
+    fn atlas() -> (GlyphAtlas, Image) {
+        let mut world = World::new();
+        world.init_resource::<Assets<Image>>();
+        world.init_resource::<Assets<StandardMaterial>>();
+        let atlas = GlyphAtlas::from_world(&mut world);
+        let images = world.resource::<Assets<Image>>();
+        let (_, image) = images.iter().next().unwrap();
+        (atlas, image.clone())
+    }
+
+    #[test]
+    fn draws_every_character_of_every_symbol() {
+        let (atlas, image) = atlas();
+        let data = image.data.as_ref().unwrap();
+        let size = image.size().as_vec2();
+        let is_drawn = |character: char| {
+            let rect = atlas.uvs[&character];
+            let (min, max) = ((rect.min * size).as_uvec2(), (rect.max * size).as_uvec2());
+            (min.y..max.y).any(|y| {
+                (min.x..max.x).any(|x| data[((y * image.width() + x) * 4 + 3) as usize] > 0)
+            })
+        };
+
+        let symbols = Operator::all()
+            .map(Symbol::Operator)
+            .into_iter()
+            .chain(UnaryOperator::all().map(Symbol::UnaryOperator))
+            .chain([Symbol::OpenParen, Symbol::CloseParen, Symbol::Equals]);
+        let texts = symbols
+            .map(|symbol| symbol.to_string())
+            .chain(["-1234567890.5".to_string()]);
+        for text in texts {
+            for character in text.chars() {
+                assert!(atlas.uvs.contains_key(&character), "{character} of {text}");
+                assert!(is_drawn(character), "{character} of {text} is blank");
+            }
+        }
+        assert!(is_drawn(FRACTION_BAR));
+    }
+}
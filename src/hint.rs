@@ -0,0 +1,217 @@
+use std::collections::BTreeSet;
+
+use bevy::prelude::*;
+
+use crate::board::{Board, Coordinates, EquationStatus};
+use crate::game_state::InGame;
+use crate::history::{self, ActionKind, Toolbar};
+use crate::player_input::Selection;
+use crate::solver::{Deduction, Solver, Technique};
+use crate::ui::UiFocus;
+
+const HINT_TEXT_COLOR: Color = Color::srgb(0.98, 0.96, 0.9);
+const HINT_BACKGROUND: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+
+#[derive(Debug, Clone)]
+pub enum Hint {
+    /// A filled in equation that does not hold.
+    Mistake { equation: usize },
+    /// The easiest blank to fill next.
+    Deduction(Deduction),
+}
+
+impl Hint {
+    /// Indices of the equations the hint is about.
+    pub fn equations(&self) -> Vec<usize> {
+        match self {
+            Hint::Mistake { equation } => vec![*equation],
+            Hint::Deduction(deduction) => deduction.equations.clone(),
+        }
+    }
+
+    pub fn cell(&self) -> Option<Coordinates> {
+        match self {
+            Hint::Mistake { .. } => None,
+            Hint::Deduction(deduction) => {
+                Some(Coordinates::new(deduction.cell.0, deduction.cell.1))
+            }
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            Hint::Mistake { .. } => "This equation does not hold.",
+            Hint::Deduction(deduction) => match deduction.technique {
+                Technique::LastCell => "This equation has only one blank left.",
+                Technique::Equation => "Only one of the tiles left fits this blank.",
+                Technique::Crossing => "Only one tile fits both equations through this blank.",
+                Technique::Guess => "Nothing can be deduced. Try a tile in this blank.",
+            },
+        }
+    }
+}
+
+/// Finds the easiest next step from the board as the player filled it in.
+/// Mistakes come first, then the blanks are deduced as if the correct
+/// entries were given.
+pub fn find_hint(board: &Board) -> Option<Hint> {
+    if let Some(equation) = board
+        .statuses
+        .iter()
+        .position(|status| *status == EquationStatus::Wrong)
+    {
+        return Some(Hint::Mistake { equation });
+    }
+
+    let open: BTreeSet<(i32, i32)> = board
+        .equations
+        .iter()
+        .flat_map(|eq| eq.cells())
+        .map(|(cell, _)| Coordinates::new(cell.0, cell.1))
+        .filter(|cell| {
+            board
+                .solution(*cell)
+                .is_some_and(|solution| board.entries.get(cell) != Some(solution))
+        })
+        .map(|cell| (cell.x, cell.y))
+        .collect();
+    Solver::new(&board.equations, &open)
+        .first_deduction()
+        .map(Hint::Deduction)
+}
+
+/// The hint shown, until the board is edited.
+#[derive(Resource, Debug)]
+pub struct ActiveHint {
+    pub hint: Hint,
+    /// `Board::moves` when the hint was given.
+    pub moves: u32,
+}
+
+impl ActiveHint {
+    /// Whether the hint points at a cell, so it is highlighted.
+    pub fn highlights(&self, board: &Board, coordinates: Coordinates) -> bool {
+        self.hint
+            .equations()
+            .iter()
+            .any(|i| board.equations[*i].contains_point((coordinates.x, coordinates.y)))
+    }
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HintButton {
+    Hint,
+    Reveal,
+}
+
+#[derive(Component)]
+pub struct HintText;
+
+pub fn setup_hints(mut commands: Commands, toolbar: Single<Entity, With<Toolbar>>) {
+    commands.entity(*toolbar).with_children(|toolbar| {
+        toolbar.spawn((history::toolbar_button("Hint"), HintButton::Hint));
+        toolbar.spawn((history::toolbar_button("Reveal"), HintButton::Reveal));
+    });
+
+    commands.spawn((
+        Name::new("Hint text"),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(64.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        StateScoped(InGame),
+        children![(
+            Node {
+                padding: UiRect::axes(Val::Px(12.0), Val::Px(6.0)),
+                display: Display::None,
+                ..default()
+            },
+            BackgroundColor(HINT_BACKGROUND),
+            BorderRadius::all(Val::Px(6.0)),
+            HintText,
+            children![(Text::new(""), TextColor(HINT_TEXT_COLOR))],
+        )],
+    ));
+}
+
+/// H or the Hint button highlights the next step, R or the Reveal button
+/// also fills in its blank. Every hint counts against the player.
+pub fn give_hint(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    buttons: Query<(&Interaction, &HintButton), Changed<Interaction>>,
+    mut board: ResMut<Board>,
+    active: Option<Res<ActiveHint>>,
+    mut selection: ResMut<Selection>,
+    ui_focus: Res<UiFocus>,
+) {
+    let mut pressed: Option<HintButton> = buttons
+        .iter()
+        .find(|(interaction, _)| **interaction == Interaction::Pressed)
+        .map(|(_, button)| *button);
+    if !ui_focus.keyboard {
+        if keyboard_input.just_pressed(KeyCode::KeyH) {
+            pressed = Some(HintButton::Hint);
+        }
+        if keyboard_input.just_pressed(KeyCode::KeyR) {
+            pressed = Some(HintButton::Reveal);
+        }
+    }
+    let Some(pressed) = pressed else {
+        return;
+    };
+
+    // Revealing the hint already shown is not another hint.
+    let hint = match active.filter(|active| active.moves == board.moves) {
+        Some(active) => active.hint.clone(),
+        None => {
+            let Some(hint) = find_hint(&board) else {
+                return;
+            };
+            board.hints += 1;
+            hint
+        }
+    };
+    if let Some(cell) = hint.cell() {
+        selection.select(cell);
+    }
+
+    if let (HintButton::Reveal, Hint::Deduction(deduction)) = (pressed, &hint) {
+        let cell = Coordinates::new(deduction.cell.0, deduction.cell.1);
+        board.edit(ActionKind::Hint, [(cell, Some(deduction.symbol.clone()))]);
+        commands.remove_resource::<ActiveHint>();
+        return;
+    }
+    commands.insert_resource(ActiveHint {
+        hint,
+        moves: board.moves,
+    });
+}
+
+/// Hides the hint once the board is edited.
+pub fn clear_stale_hint(mut commands: Commands, board: Res<Board>, active: Res<ActiveHint>) {
+    if active.moves != board.moves {
+        commands.remove_resource::<ActiveHint>();
+    }
+}
+
+pub fn update_hint_text(
+    active: Option<Res<ActiveHint>>,
+    hint_text: Single<(&mut Node, &Children), With<HintText>>,
+    mut texts: Query<&mut Text>,
+) {
+    let (mut node, children) = hint_text.into_inner();
+    let Some(active) = active else {
+        node.display = Display::None;
+        return;
+    };
+    node.display = Display::Flex;
+    for child in children.iter() {
+        if let Ok(mut text) = texts.get_mut(child) {
+            text.0 = active.hint.message().to_string();
+        }
+    }
+}
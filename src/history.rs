@@ -0,0 +1,159 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, Coordinates};
+use crate::equation::Symbol;
+use crate::game_state::InGame;
+use crate::ui::UiFocus;
+
+const BUTTON_COLOR: Color = Color::srgb(0.95, 0.88, 0.7);
+const BUTTON_TEXT_COLOR: Color = Color::srgb(0.15, 0.1, 0.05);
+// Alpha of a button with nothing to undo or redo.
+const DISABLED_ALPHA: f32 = 0.4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActionKind {
+    /// The player placed, moved or cleared tiles.
+    Play,
+    /// A hint revealed a blank.
+    Hint,
+}
+
+/// A change of one blank.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Edit {
+    pub cell: Coordinates,
+    pub before: Option<Symbol>,
+    pub after: Option<Symbol>,
+}
+
+/// Everything one player action changed on the board, so it can be undone
+/// as a whole.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Action {
+    pub kind: ActionKind,
+    pub edits: Vec<Edit>,
+}
+
+/// The actions on the board, oldest first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct History {
+    pub done: Vec<Action>,
+    /// Undone actions, most recently undone last.
+    pub undone: Vec<Action>,
+}
+
+impl History {
+    /// Records a new action. Whatever was undone can no longer be redone.
+    pub fn record(&mut self, action: Action) {
+        self.done.push(action);
+        self.undone.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+}
+
+/// The row of buttons at the top of the screen.
+#[derive(Component)]
+pub struct Toolbar;
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryButton {
+    Undo,
+    Redo,
+}
+
+pub fn setup_history_buttons(mut commands: Commands) {
+    commands
+        .spawn((
+            Name::new("Toolbar"),
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(16.0),
+                right: Val::Px(16.0),
+                column_gap: Val::Px(8.0),
+                ..default()
+            },
+            Toolbar,
+            StateScoped(InGame),
+        ))
+        .with_children(|toolbar| {
+            toolbar.spawn((toolbar_button("Undo"), HistoryButton::Undo));
+            toolbar.spawn((toolbar_button("Redo"), HistoryButton::Redo));
+        });
+}
+
+pub fn toolbar_button(label: &str) -> impl Bundle {
+    (
+        Button,
+        Node {
+            padding: UiRect::axes(Val::Px(14.0), Val::Px(8.0)),
+            ..default()
+        },
+        BackgroundColor(BUTTON_COLOR),
+        BorderRadius::all(Val::Px(6.0)),
+        children![(Text::new(label), TextColor(BUTTON_TEXT_COLOR))],
+    )
+}
+
+/// Dims the buttons when there is nothing to undo or redo.
+pub fn update_history_buttons(
+    board: Res<Board>,
+    mut buttons: Query<(&HistoryButton, &mut BackgroundColor)>,
+) {
+    for (button, mut color) in buttons.iter_mut() {
+        let enabled = match button {
+            HistoryButton::Undo => board.history.can_undo(),
+            HistoryButton::Redo => board.history.can_redo(),
+        };
+        let alpha = if enabled { 1.0 } else { DISABLED_ALPHA };
+        color.0 = BUTTON_COLOR.with_alpha(alpha);
+    }
+}
+
+/// Undoes with Ctrl+Z and the Undo button, redoes with Ctrl+Y, Ctrl+Shift+Z
+/// and the Redo button.
+pub fn undo_redo(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    buttons: Query<(&Interaction, &HistoryButton), Changed<Interaction>>,
+    mut board: ResMut<Board>,
+    ui_focus: Res<UiFocus>,
+) {
+    let mut pressed: Vec<HistoryButton> = buttons
+        .iter()
+        .filter(|(interaction, _)| **interaction == Interaction::Pressed)
+        .map(|(_, button)| *button)
+        .collect();
+
+    let ctrl = !ui_focus.keyboard
+        && keyboard_input.any_pressed([
+            KeyCode::ControlLeft,
+            KeyCode::ControlRight,
+            KeyCode::SuperLeft,
+            KeyCode::SuperRight,
+        ]);
+    let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if ctrl && keyboard_input.just_pressed(KeyCode::KeyZ) {
+        pressed.push(if shift {
+            HistoryButton::Redo
+        } else {
+            HistoryButton::Undo
+        });
+    }
+    if ctrl && keyboard_input.just_pressed(KeyCode::KeyY) {
+        pressed.push(HistoryButton::Redo);
+    }
+
+    for button in pressed {
+        match button {
+            HistoryButton::Undo => board.undo(),
+            HistoryButton::Redo => board.redo(),
+        };
+    }
+}
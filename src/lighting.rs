@@ -1,287 +1,234 @@
-//! Illustrates different lights of various types and colors, some static, some moving over
-//! a simple scene.
+use bevy::core_pipeline::bloom::Bloom;
+use bevy::core_pipeline::tonemapping::Tonemapping;
+use bevy::pbr::CascadeShadowConfigBuilder;
+use bevy::prelude::*;
+use bevy::render::camera::{Exposure, PhysicalCameraParameters};
+
+use crate::board::TILE_DEPTH;
+use crate::camera::SceneCamera;
+use crate::texture_manager::TextureManager;
+
+// Large enough to fill the view zoomed out all the way.
+const BACKDROP_SIZE: f32 = 100_000.0;
+// Shadows are cast over the board, which is a few hundred units from the
+// camera.
+const SHADOW_DISTANCE: f32 = 2_000.0;
+// Super 35, as in the exposure example of Bevy.
+const SENSOR_HEIGHT: f32 = 0.01866;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LightingPreset {
+    /// Soft white light from the top left, like a photo studio.
+    #[default]
+    Studio,
+    /// A dim purple scene lit by the emissive maps of the panels, with bloom.
+    Neon,
+    /// Cold moonlight for playing in the dark.
+    Night,
+}
+
+/// How a preset lights the board and what the camera does with it.
+struct PresetSettings {
+    light_color: Color,
+    illuminance: f32,
+    /// Where the light comes from, seen from the board.
+    light_from: Vec3,
+    ambient_color: Color,
+    ambient_brightness: f32,
+    environment_intensity: f32,
+    /// Colour of the plane behind the board, which the shadows fall on.
+    backdrop: Color,
+    /// Extra brightness of the emissive maps of the tiles.
+    glow: f32,
+    bloom: Option<Bloom>,
+    tonemapping: Tonemapping,
+    shadows: bool,
+    exposure: PhysicalCameraParameters,
+}
+
+impl LightingPreset {
+    pub fn all() -> [LightingPreset; 3] {
+        [
+            LightingPreset::Studio,
+            LightingPreset::Neon,
+            LightingPreset::Night,
+        ]
+    }
+
+    fn settings(&self) -> PresetSettings {
+        match self {
+            LightingPreset::Studio => PresetSettings {
+                light_color: Color::WHITE,
+                illuminance: 1_500.0,
+                light_from: Vec3::new(-1.0, 1.0, 2.0),
+                ambient_color: Color::WHITE,
+                ambient_brightness: 80.0,
+                environment_intensity: 900.0,
+                backdrop: Color::srgb(0.0, 0.21, 0.56),
+                glow: 0.0,
+                bloom: None,
+                tonemapping: Tonemapping::TonyMcMapface,
+                shadows: true,
+                exposure: camera_parameters(4.0, 1.0 / 60.0, 100.0),
+            },
+            LightingPreset::Neon => PresetSettings {
+                light_color: Color::srgb(0.6, 0.4, 1.0),
+                illuminance: 400.0,
+                light_from: Vec3::new(0.0, 1.0, 3.0),
+                ambient_color: Color::srgb(0.5, 0.3, 1.0),
+                ambient_brightness: 40.0,
+                environment_intensity: 200.0,
+                backdrop: Color::srgb(0.03, 0.01, 0.08),
+                glow: 6.0,
+                bloom: Some(Bloom::NATURAL),
+                tonemapping: Tonemapping::AgX,
+                shadows: false,
+                exposure: camera_parameters(2.8, 1.0 / 60.0, 100.0),
+            },
+            LightingPreset::Night => PresetSettings {
+                light_color: Color::srgb(0.65, 0.75, 1.0),
+                illuminance: 300.0,
+                light_from: Vec3::new(1.0, 0.6, 1.0),
+                ambient_color: Color::srgb(0.3, 0.4, 0.8),
+                ambient_brightness: 20.0,
+                environment_intensity: 100.0,
+                backdrop: Color::srgb(0.01, 0.02, 0.05),
+                glow: 1.0,
+                bloom: Some(Bloom {
+                    intensity: 0.1,
+                    ..Bloom::NATURAL
+                }),
+                tonemapping: Tonemapping::BlenderFilmic,
+                shadows: true,
+                exposure: camera_parameters(2.8, 1.0 / 60.0, 100.0),
+            },
+        }
+    }
+}
+
+fn camera_parameters(
+    aperture_f_stops: f32,
+    shutter_speed_s: f32,
+    sensitivity_iso: f32,
+) -> PhysicalCameraParameters {
+    PhysicalCameraParameters {
+        aperture_f_stops,
+        shutter_speed_s,
+        sensitivity_iso,
+        sensor_height: SENSOR_HEIGHT,
+    }
+}
 
-use std::f32::consts::PI;
+/// The lighting preset picked, and what of it the player changed.
+#[derive(Resource, Clone, Copy)]
+pub struct SceneLighting {
+    pub preset: LightingPreset,
+    pub shadows: bool,
+    /// The exposure of the scene camera, tuned in the lighting panel.
+    pub exposure: PhysicalCameraParameters,
+}
 
-use bevy::{
-    color::palettes::css::*,
-    pbr::CascadeShadowConfigBuilder,
-    prelude::*,
-    render::camera::{Exposure, PhysicalCameraParameters},
-};
+impl SceneLighting {
+    /// The preset as it is set up, undoing any changes to it.
+    pub fn new(preset: LightingPreset) -> Self {
+        let settings = preset.settings();
+        Self {
+            preset,
+            shadows: settings.shadows,
+            exposure: settings.exposure,
+        }
+    }
+}
 
-pub fn main_lighting() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .insert_resource(Parameters(PhysicalCameraParameters {
-            aperture_f_stops: 1.0,
-            shutter_speed_s: 1.0 / 125.0,
-            sensitivity_iso: 100.0,
-            sensor_height: 0.01866,
-        }))
-        .add_systems(Startup, setup)
-        .add_systems(Update, (update_exposure, animate_light_direction))
-        .run();
+impl Default for SceneLighting {
+    fn default() -> Self {
+        Self::new(LightingPreset::default())
+    }
 }
 
-#[derive(Resource, Default, Deref, DerefMut)]
-struct Parameters(PhysicalCameraParameters);
+/// The light of the scene.
+#[derive(Component)]
+pub struct SceneLight;
 
+/// The plane behind the board.
 #[derive(Component)]
-struct Movable;
+pub struct Backdrop;
 
-/// set up a simple 3D scene
-fn setup(
-    parameters: Res<Parameters>,
+pub fn setup_lighting(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    asset_server: Res<AssetServer>,
 ) {
-    // ground plane
-    commands.spawn((
-        Mesh3d(meshes.add(Plane3d::default().mesh().size(10.0, 10.0))),
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color: Color::WHITE,
-            perceptual_roughness: 1.0,
-            ..default()
-        })),
-    ));
-
-    // left wall
-    let mut transform = Transform::from_xyz(2.5, 2.5, 0.0);
-    transform.rotate_z(PI / 2.);
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(5.0, 0.15, 5.0))),
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color: INDIGO.into(),
-            perceptual_roughness: 1.0,
-            ..default()
-        })),
-        transform,
-    ));
-    // back (right) wall
-    let mut transform = Transform::from_xyz(0.0, 2.5, -2.5);
-    transform.rotate_x(PI / 2.);
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(5.0, 0.15, 5.0))),
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color: INDIGO.into(),
-            perceptual_roughness: 1.0,
-            ..default()
-        })),
-        transform,
-    ));
-
-    // Bevy logo to demonstrate alpha mask shadows
-    let mut transform = Transform::from_xyz(-2.2, 0.5, 1.0);
-    transform.rotate_y(PI / 8.);
-    commands.spawn((
-        Mesh3d(meshes.add(Rectangle::new(2.0, 0.5))),
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color_texture: Some(asset_server.load("branding/bevy_logo_light.png")),
-            perceptual_roughness: 1.0,
-            alpha_mode: AlphaMode::Mask(0.5),
-            cull_mode: None,
-            ..default()
-        })),
-        transform,
-        Movable,
-    ));
-
-    // cube
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::default())),
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color: DEEP_PINK.into(),
-            ..default()
-        })),
-        Transform::from_xyz(0.0, 0.5, 0.0),
-        Movable,
-    ));
-    // sphere
-    commands.spawn((
-        Mesh3d(meshes.add(Sphere::new(0.5).mesh().uv(32, 18))),
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color: LIMEGREEN.into(),
-            ..default()
-        })),
-        Transform::from_xyz(1.5, 1.0, 1.5),
-        Movable,
-    ));
-
-    // ambient light
-    commands.insert_resource(AmbientLight {
-        color: ORANGE_RED.into(),
-        brightness: 0.02,
-        ..default()
-    });
-
-    // red point light
-    commands.spawn((
-        PointLight {
-            intensity: 100_000.0,
-            color: RED.into(),
-            shadows_enabled: true,
-            ..default()
-        },
-        Transform::from_xyz(1.0, 2.0, 0.0),
-        children![(
-            Mesh3d(meshes.add(Sphere::new(0.1).mesh().uv(32, 18))),
-            MeshMaterial3d(materials.add(StandardMaterial {
-                base_color: RED.into(),
-                emissive: LinearRgba::new(4.0, 0.0, 0.0, 0.0),
-                ..default()
-            })),
-        )],
-    ));
-
-    // green spot light
-    commands.spawn((
-        SpotLight {
-            intensity: 100_000.0,
-            color: LIME.into(),
-            shadows_enabled: true,
-            inner_angle: 0.6,
-            outer_angle: 0.8,
-            ..default()
-        },
-        Transform::from_xyz(-1.0, 2.0, 0.0).looking_at(Vec3::new(-1.0, 0.0, 0.0), Vec3::Z),
-        children![(
-            Mesh3d(meshes.add(Capsule3d::new(0.1, 0.125))),
-            MeshMaterial3d(materials.add(StandardMaterial {
-                base_color: LIME.into(),
-                emissive: LinearRgba::new(0.0, 4.0, 0.0, 0.0),
-                ..default()
-            })),
-            Transform::from_rotation(Quat::from_rotation_x(PI / 2.0)),
-        )],
-    ));
-
-    // blue point light
     commands.spawn((
-        PointLight {
-            intensity: 100_000.0,
-            color: BLUE.into(),
-            shadows_enabled: true,
-            ..default()
-        },
-        Transform::from_xyz(0.0, 4.0, 0.0),
-        children![(
-            Mesh3d(meshes.add(Sphere::new(0.1).mesh().uv(32, 18))),
-            MeshMaterial3d(materials.add(StandardMaterial {
-                base_color: BLUE.into(),
-                emissive: LinearRgba::new(0.0, 0.0, 713.0, 0.0),
-                ..default()
-            })),
-        )],
-    ));
-
-    // directional 'sun' light
-    commands.spawn((
-        DirectionalLight {
-            illuminance: light_consts::lux::OVERCAST_DAY,
-            shadows_enabled: true,
-            ..default()
-        },
-        Transform {
-            translation: Vec3::new(0.0, 2.0, 0.0),
-            rotation: Quat::from_rotation_x(-PI / 4.),
-            ..default()
-        },
-        // The default cascade config is designed to handle large scenes.
-        // As this example has a much smaller world, we can tighten the shadow
-        // bounds for better visual quality.
+        Name::new("Scene light"),
+        DirectionalLight::default(),
+        Transform::default(),
         CascadeShadowConfigBuilder {
-            first_cascade_far_bound: 4.0,
-            maximum_distance: 10.0,
+            num_cascades: 1,
+            first_cascade_far_bound: SHADOW_DISTANCE,
+            maximum_distance: SHADOW_DISTANCE,
             ..default()
         }
         .build(),
+        SceneLight,
     ));
 
-    // example instructions
-
+    // Behind the cursor, which is behind the tiles.
     commands.spawn((
-        Text::default(),
-        Node {
-            position_type: PositionType::Absolute,
-            top: Val::Px(12.0),
-            left: Val::Px(12.0),
+        Name::new("Backdrop"),
+        Mesh3d(meshes.add(Rectangle::new(BACKDROP_SIZE, BACKDROP_SIZE))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            perceptual_roughness: 1.0,
             ..default()
-        },
-        children![
-            TextSpan(format!("Aperture: f/{:.0}\n", parameters.aperture_f_stops,)),
-            TextSpan(format!(
-                "Shutter speed: 1/{:.0}s\n",
-                1.0 / parameters.shutter_speed_s
-            )),
-            TextSpan(format!(
-                "Sensitivity: ISO {:.0}\n",
-                parameters.sensitivity_iso
-            )),
-            TextSpan::new("\n\n"),
-            TextSpan::new("Controls\n"),
-            TextSpan::new("---------------\n"),
-            TextSpan::new("Arrow keys - Move objects\n"),
-            TextSpan::new("1/2 - Decrease/Increase aperture\n"),
-            TextSpan::new("3/4 - Decrease/Increase shutter speed\n"),
-            TextSpan::new("5/6 - Decrease/Increase sensitivity\n"),
-            TextSpan::new("R - Reset exposure"),
-        ],
-    ));
-
-    // camera
-    commands.spawn((
-        Camera3d::default(),
-        Transform::from_xyz(-2.0, 2.5, 5.0).looking_at(Vec3::ZERO, Vec3::Y),
-        Exposure::from_physical_camera(**parameters),
+        })),
+        Transform::from_xyz(0.0, 0.0, -TILE_DEPTH - 1.0),
+        Backdrop,
     ));
 }
 
-fn update_exposure(
-    key_input: Res<ButtonInput<KeyCode>>,
-    mut parameters: ResMut<Parameters>,
-    mut exposure: Single<&mut Exposure>,
-    text: Single<Entity, With<Text>>,
-    mut writer: TextUiWriter,
+/// Sets up the lights, the backdrop and the glow of the tiles for the preset.
+pub fn apply_lights(
+    lighting: Res<SceneLighting>,
+    light: Single<(&mut DirectionalLight, &mut Transform), With<SceneLight>>,
+    backdrop: Single<&MeshMaterial3d<StandardMaterial>, With<Backdrop>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut ambient: ResMut<AmbientLight>,
+    mut textures: ResMut<TextureManager>,
 ) {
-    // TODO: Clamp values to a reasonable range
-    let entity = *text;
-    if key_input.just_pressed(KeyCode::Digit2) {
-        parameters.aperture_f_stops *= 2.0;
-    } else if key_input.just_pressed(KeyCode::Digit1) {
-        parameters.aperture_f_stops *= 0.5;
-    }
-    if key_input.just_pressed(KeyCode::Digit4) {
-        parameters.shutter_speed_s *= 2.0;
-    } else if key_input.just_pressed(KeyCode::Digit3) {
-        parameters.shutter_speed_s *= 0.5;
-    }
-    if key_input.just_pressed(KeyCode::Digit6) {
-        parameters.sensitivity_iso += 100.0;
-    } else if key_input.just_pressed(KeyCode::Digit5) {
-        parameters.sensitivity_iso -= 100.0;
+    let settings = lighting.preset.settings();
+    let (mut light, mut transform) = light.into_inner();
+    light.color = settings.light_color;
+    light.illuminance = settings.illuminance;
+    light.shadows_enabled = lighting.shadows;
+    *transform = Transform::from_translation(settings.light_from).looking_at(Vec3::ZERO, Vec3::Y);
+
+    if let Some(material) = materials.get_mut(&backdrop.0) {
+        material.base_color = settings.backdrop;
     }
-    if key_input.just_pressed(KeyCode::KeyR) {
-        *parameters = Parameters::default();
+    ambient.color = settings.ambient_color;
+    ambient.brightness = settings.ambient_brightness;
+    if textures.glow != settings.glow {
+        textures.glow = settings.glow;
     }
-
-    *writer.text(entity, 1) = format!("Aperture: f/{:.0}\n", parameters.aperture_f_stops);
-    *writer.text(entity, 2) = format!(
-        "Shutter speed: 1/{:.0}s\n",
-        1.0 / parameters.shutter_speed_s
-    );
-    *writer.text(entity, 3) = format!("Sensitivity: ISO {:.0}\n", parameters.sensitivity_iso);
-
-    **exposure = Exposure::from_physical_camera(**parameters);
 }
 
-fn animate_light_direction(
-    time: Res<Time>,
-    mut query: Query<&mut Transform, With<DirectionalLight>>,
+/// Sets up bloom, tone mapping and exposure of the scene camera.
+pub fn apply_post_processing(
+    mut commands: Commands,
+    lighting: Res<SceneLighting>,
+    camera: Single<(Entity, &mut EnvironmentMapLight), With<SceneCamera>>,
 ) {
-    for mut transform in &mut query {
-        transform.rotate_y(time.delta_secs() * 0.5);
-    }
+    let settings = lighting.preset.settings();
+    let (entity, mut environment) = camera.into_inner();
+    environment.intensity = settings.environment_intensity;
+
+    let mut camera = commands.entity(entity);
+    camera.insert((
+        settings.tonemapping,
+        Exposure::from_physical_camera(lighting.exposure),
+    ));
+    match settings.bloom {
+        Some(bloom) => camera.insert(bloom),
+        None => camera.remove::<Bloom>(),
+    };
 }
@@ -3,15 +3,31 @@ use bevy::input;
 use bevy_egui::EguiPlugin;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use crossequa_plugin::CrossequaPlugin;
-// use lighting::main_lighting;
 
+// A demo app of its own, started by hand from `main`.
+#[allow(dead_code)]
 mod atlas_test;
 mod board;
+mod camera;
 mod crossequa_plugin;
-// mod lighting;
+mod difficulty;
 mod equation;
+mod evaluator;
+mod game_state;
+mod glyph_atlas;
+mod hint;
+mod history;
+mod lighting;
+mod number;
+mod placement;
 mod player_input;
+mod progress;
+mod rack;
+mod save;
+mod solver;
 mod texture_manager;
+mod tile_animation;
+mod ui;
 
 fn main() {
     //run_atlas_test();
@@ -22,7 +38,7 @@ fn main() {
             enable_multipass_for_primary_context: true,
         })
         .add_plugins(WorldInspectorPlugin::default().run_if(
-            input::common_conditions::input_toggle_active(true, input::prelude::KeyCode::Tab),
+            input::common_conditions::input_toggle_active(true, input::prelude::KeyCode::F1),
         ))
         .run();
 }
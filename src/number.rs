@@ -0,0 +1,436 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+// Decimals with more places than this are written as fractions instead.
+const MAX_DECIMAL_PLACES: u32 = 3;
+
+/// An exact fraction, always stored in lowest terms with a positive
+/// denominator. Saved as a `(numerator, denominator)` pair that is brought
+/// into lowest terms again when loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "(i64, i64)", into = "(i64, i64)")]
+pub struct Rational {
+    numerator: i64,
+    denominator: i64,
+}
+
+impl TryFrom<(i64, i64)> for Rational {
+    type Error = &'static str;
+
+    fn try_from((numerator, denominator): (i64, i64)) -> Result<Self, Self::Error> {
+        Rational::new(numerator, denominator).ok_or("denominator is zero")
+    }
+}
+
+impl From<Rational> for (i64, i64) {
+    fn from(rational: Rational) -> Self {
+        (rational.numerator, rational.denominator)
+    }
+}
+
+impl Rational {
+    pub fn new(numerator: i64, denominator: i64) -> Option<Self> {
+        if denominator == 0 {
+            return None;
+        }
+        let divisor = gcd(numerator, denominator);
+        let sign = if denominator < 0 { -1 } else { 1 };
+        Some(Self {
+            numerator: sign * numerator / divisor,
+            denominator: sign * denominator / divisor,
+        })
+    }
+
+    pub fn integer(n: i64) -> Self {
+        Self {
+            numerator: n,
+            denominator: 1,
+        }
+    }
+
+    pub fn numerator(&self) -> i64 {
+        self.numerator
+    }
+
+    pub fn denominator(&self) -> i64 {
+        self.denominator
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denominator == 1
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.numerator == 0
+    }
+
+    pub fn checked_add(&self, other: &Rational) -> Option<Rational> {
+        let numerator = self
+            .numerator
+            .checked_mul(other.denominator)?
+            .checked_add(other.numerator.checked_mul(self.denominator)?)?;
+        Rational::new(numerator, self.denominator.checked_mul(other.denominator)?)
+    }
+
+    pub fn checked_sub(&self, other: &Rational) -> Option<Rational> {
+        self.checked_add(&Rational {
+            numerator: other.numerator.checked_neg()?,
+            denominator: other.denominator,
+        })
+    }
+
+    pub fn checked_mul(&self, other: &Rational) -> Option<Rational> {
+        Rational::new(
+            self.numerator.checked_mul(other.numerator)?,
+            self.denominator.checked_mul(other.denominator)?,
+        )
+    }
+
+    pub fn checked_div(&self, other: &Rational) -> Option<Rational> {
+        Rational::new(
+            self.numerator.checked_mul(other.denominator)?,
+            self.denominator.checked_mul(other.numerator)?,
+        )
+    }
+
+    pub fn checked_pow(&self, exponent: i64) -> Option<Rational> {
+        let base = if exponent < 0 {
+            Rational::integer(1).checked_div(self)?
+        } else {
+            *self
+        };
+        let exponent = u32::try_from(exponent.unsigned_abs()).ok()?;
+        Rational::new(
+            base.numerator.checked_pow(exponent)?,
+            base.denominator.checked_pow(exponent)?,
+        )
+    }
+
+    /// The exact `degree`-th root, if it is rational.
+    pub fn exact_root(&self, degree: u32) -> Option<Rational> {
+        if degree == 0 || (self.numerator < 0 && degree.is_multiple_of(2)) {
+            return None;
+        }
+        let numerator = integer_root(self.numerator.abs(), degree)? * self.numerator.signum();
+        let denominator = integer_root(self.denominator, degree)?;
+        Rational::new(numerator, denominator)
+    }
+
+    /// The integer `k` with `base^k = self`, if there is one.
+    pub fn exact_log(&self, base: &Rational) -> Option<i64> {
+        if *base <= Rational::integer(0)
+            || *base == Rational::integer(1)
+            || *self <= Rational::integer(0)
+        {
+            return None;
+        }
+
+        // Work with a base greater than one, flipping the sign of the result
+        // for bases below one.
+        let (base, sign) = if *base > Rational::integer(1) {
+            (*base, 1)
+        } else {
+            (Rational::integer(1).checked_div(base)?, -1)
+        };
+        let (target, sign) = if *self >= Rational::integer(1) {
+            (*self, sign)
+        } else {
+            (Rational::integer(1).checked_div(self)?, -sign)
+        };
+
+        let mut power = Rational::integer(1);
+        let mut k = 0;
+        while power < target {
+            power = power.checked_mul(&base)?;
+            k += 1;
+        }
+        (power == target).then_some(sign * k)
+    }
+
+    // Number of decimal places needed to write this value exactly, if any.
+    fn decimal_places(&self) -> Option<u32> {
+        let mut denominator = self.denominator;
+        let (mut twos, mut fives) = (0, 0);
+        while denominator % 2 == 0 {
+            denominator /= 2;
+            twos += 1;
+        }
+        while denominator % 5 == 0 {
+            denominator /= 5;
+            fives += 1;
+        }
+        (denominator == 1).then_some(u32::max(twos, fives))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.numerator as i128 * other.denominator as i128)
+            .cmp(&(other.numerator as i128 * self.denominator as i128))
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.max(1)
+}
+
+fn integer_root(x: i64, degree: u32) -> Option<i64> {
+    let guess = (x as f64).powf(1.0 / degree as f64).round() as i64;
+    (guess.saturating_sub(1)..=guess.saturating_add(1))
+        .find(|root| *root >= 0 && root.checked_pow(degree) == Some(x))
+}
+
+/// A number as it is written on a tile. Two numbers with the same value but a
+/// different notation, such as `1/2` and `0.5`, are different symbols.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Number {
+    Integer(i64),
+    Fraction(Rational),
+    /// `units / 10^places`, always written with exactly `places` decimals.
+    Decimal {
+        units: i64,
+        places: u32,
+    },
+}
+
+impl Number {
+    pub fn value(&self) -> Rational {
+        match self {
+            Number::Integer(n) => Rational::integer(*n),
+            Number::Fraction(r) => *r,
+            Number::Decimal { units, places } => {
+                Rational::new(*units, 10_i64.pow(*places)).expect("powers of ten are never zero")
+            }
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        let value = self.value();
+        value.is_integer().then_some(value.numerator())
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.value().numerator() < 0
+    }
+
+    pub fn is_fraction(&self) -> bool {
+        matches!(self, Number::Fraction(_))
+    }
+
+    pub fn is_decimal(&self) -> bool {
+        matches!(self, Number::Decimal { .. })
+    }
+
+    pub fn fraction(numerator: i64, denominator: i64) -> Option<Number> {
+        Some(Number::from_value(
+            Rational::new(numerator, denominator)?,
+            true,
+        ))
+    }
+
+    /// Writes `value` in the simplest notation: integers stay integers,
+    /// otherwise a fraction if `prefer_fraction` is set or the value has no
+    /// short decimal expansion, and a decimal if not.
+    pub fn from_value(value: Rational, prefer_fraction: bool) -> Number {
+        if value.is_integer() {
+            return Number::Integer(value.numerator());
+        }
+
+        match value.decimal_places() {
+            Some(places) if !prefer_fraction && places <= MAX_DECIMAL_PLACES => {
+                let units = value.numerator() * 10_i64.pow(places) / value.denominator();
+                Number::Decimal { units, places }
+            }
+            _ => Number::Fraction(value),
+        }
+    }
+
+    /// Notation of the result of combining `self` with `other`: fractions win
+    /// over decimals, decimals over integers.
+    pub fn combine(&self, other: &Number, value: Rational) -> Number {
+        let prefer_fraction =
+            self.is_fraction() || other.is_fraction() || !(self.is_decimal() || other.is_decimal());
+        Number::from_value(value, prefer_fraction)
+    }
+
+    /// Whether the number is short enough to fit on a tile.
+    pub fn is_simple(&self, max_denominator: i64) -> bool {
+        match self {
+            Number::Integer(n) => n.abs() < 1000,
+            Number::Fraction(r) => r.denominator() <= max_denominator && r.numerator().abs() < 100,
+            Number::Decimal { units, places } => {
+                *places <= MAX_DECIMAL_PLACES && units.abs() < 10_000
+            }
+        }
+    }
+}
+
+impl From<i64> for Number {
+    fn from(n: i64) -> Self {
+        Number::Integer(n)
+    }
+}
+
+/// A number typed by the player that is not an integer, a fraction like
+/// `3/4` or a decimal like `-0.5`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseNumberError;
+
+impl std::fmt::Display for ParseNumberError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "not a number")
+    }
+}
+
+impl std::str::FromStr for Number {
+    type Err = ParseNumberError;
+
+    /// Parses integers, fractions and decimals, in the simplest notation of
+    /// their value, so `4/2` is `2` and `1.50` is `1.5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((numerator, denominator)) = s.split_once('/') {
+            let numerator = numerator.parse().map_err(|_| ParseNumberError)?;
+            let denominator = denominator.parse().map_err(|_| ParseNumberError)?;
+            return Number::fraction(numerator, denominator).ok_or(ParseNumberError);
+        }
+
+        if let Some((whole, frac)) = s.split_once('.') {
+            if whole.is_empty() || frac.is_empty() || !frac.chars().all(|c| c.is_ascii_digit()) {
+                return Err(ParseNumberError);
+            }
+            let sign = if whole.starts_with('-') { -1 } else { 1 };
+            let whole: i64 = whole.parse().map_err(|_| ParseNumberError)?;
+            let scale = 10_i64
+                .checked_pow(frac.len() as u32)
+                .ok_or(ParseNumberError)?;
+            let frac: i64 = frac.parse().map_err(|_| ParseNumberError)?;
+            let units = whole
+                .abs()
+                .checked_mul(scale)
+                .and_then(|units| units.checked_add(frac))
+                .ok_or(ParseNumberError)?;
+            let value = Rational::new(sign * units, scale).ok_or(ParseNumberError)?;
+            return Ok(Number::from_value(value, false));
+        }
+
+        s.parse().map(Number::Integer).map_err(|_| ParseNumberError)
+    }
+}
+
+impl std::fmt::Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Number::Integer(n) => write!(f, "{n}"),
+            Number::Fraction(r) => write!(f, "{}/{}", r.numerator(), r.denominator()),
+            Number::Decimal { units, places } => {
+                let scale = 10_i64.pow(*places);
+                let sign = if *units < 0 { "-" } else { "" };
+                let (whole, frac) = (units.abs() / scale, units.abs() % scale);
+                write!(f, "{sign}{whole}.{frac:0width$}", width = *places as usize)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rational(numerator: i64, denominator: i64) -> Rational {
+        Rational::new(numerator, denominator).unwrap()
+    }
+
+    #[test]
+    fn keeps_lowest_terms() {
+        let half = rational(2, -4);
+        assert_eq!((half.numerator(), half.denominator()), (-1, 2));
+        assert_eq!(rational(0, 5), Rational::integer(0));
+        assert_eq!(
+            rational(1, 6).checked_add(&rational(1, 3)),
+            Some(rational(1, 2))
+        );
+        assert_eq!(
+            rational(3, 4).checked_mul(&rational(2, 3)),
+            Some(rational(1, 2))
+        );
+    }
+
+    #[test]
+    fn rejects_division_by_zero() {
+        assert_eq!(Rational::new(1, 0), None);
+        assert_eq!(rational(3, 4).checked_div(&Rational::integer(0)), None);
+        assert_eq!(Rational::integer(0).checked_pow(-1), None);
+        assert_eq!(Number::fraction(1, 0), None);
+        assert_eq!("1/0".parse::<Number>(), Err(ParseNumberError));
+    }
+
+    #[test]
+    fn loads_fractions_in_lowest_terms() {
+        let saved = ron::to_string(&rational(-1, 2)).unwrap();
+        assert_eq!(saved, "(-1,2)");
+        assert_eq!(ron::from_str::<Rational>(&saved), Ok(rational(-1, 2)));
+        assert_eq!(ron::from_str::<Rational>("(2,-4)"), Ok(rational(-1, 2)));
+        assert!(ron::from_str::<Rational>("(1,0)").is_err());
+    }
+
+    #[test]
+    fn reports_overflow() {
+        let large = Rational::integer(i64::MAX);
+        assert_eq!(large.checked_add(&Rational::integer(1)), None);
+        assert_eq!(large.checked_mul(&Rational::integer(2)), None);
+        assert_eq!(Rational::integer(10).checked_pow(19), None);
+    }
+
+    #[test]
+    fn finds_exact_roots_and_logarithms() {
+        assert_eq!(rational(8, 27).exact_root(3), Some(rational(2, 3)));
+        assert_eq!(Rational::integer(2).exact_root(2), None);
+        assert_eq!(Rational::integer(-4).exact_root(2), None);
+        assert_eq!(
+            Rational::integer(1000).exact_log(&Rational::integer(10)),
+            Some(3)
+        );
+        assert_eq!(rational(1, 8).exact_log(&Rational::integer(2)), Some(-3));
+        assert_eq!(Rational::integer(10).exact_log(&Rational::integer(3)), None);
+    }
+
+    #[test]
+    fn writes_numbers_in_their_notation() {
+        assert_eq!(
+            Number::from_value(rational(1, 4), false).to_string(),
+            "0.25"
+        );
+        assert_eq!(Number::from_value(rational(1, 4), true).to_string(), "1/4");
+        assert_eq!(Number::from_value(rational(1, 3), false).to_string(), "1/3");
+        assert_eq!(
+            Number::from_value(rational(-3, 2), false).to_string(),
+            "-1.5"
+        );
+        assert_eq!("1.50".parse::<Number>().unwrap().to_string(), "1.5");
+        assert_eq!("4/2".parse::<Number>(), Ok(Number::Integer(2)));
+        assert_eq!("-0.05".parse::<Number>().unwrap().to_string(), "-0.05");
+    }
+
+    #[test]
+    fn simple_decimals_have_few_places() {
+        let short = Number::from_value(rational(1, 8), false);
+        assert_eq!(short.to_string(), "0.125");
+        assert!(short.is_simple(12));
+        let long = Number::Decimal {
+            units: 1,
+            places: MAX_DECIMAL_PLACES + 1,
+        };
+        assert!(!long.is_simple(12));
+    }
+}
@@ -0,0 +1,522 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use bevy::log::warn;
+
+use rand::Rng;
+use rand::prelude::IndexedRandom;
+
+use crate::equation::{
+    Bounds, Direction, EquationGenerator, GridEquation, LayoutConstraints, Symbol,
+};
+
+// Upper bound on the number of candidates tried before backtracking, for the
+// sparsest and the densest layouts.
+const CANDIDATES_PER_STEP: usize = 16;
+const DENSE_CANDIDATES_PER_STEP: usize = 128;
+// Upper bound on the total number of candidates tried for a single layout.
+const MAX_STEPS: usize = 8000;
+const MAX_RESTARTS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlacementError {
+    /// Two different symbols would end up in the same cell.
+    Conflict((i32, i32)),
+    /// Equations may only cross on a number both of them share.
+    NonNumberCrossing((i32, i32)),
+    /// The candidate runs along an equation with the same direction.
+    ParallelOverlap((i32, i32)),
+    /// The candidate touches a cell of another equation without crossing it.
+    TouchingNeighbour((i32, i32)),
+    /// The layout would grow beyond the `LayoutConstraints`.
+    OutOfBounds,
+    /// The candidate equation does not hold.
+    InvalidEquation,
+}
+
+impl std::fmt::Display for PlacementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlacementError::Conflict(pos) => write!(f, "conflicting symbols at {pos:?}"),
+            PlacementError::NonNumberCrossing(pos) => {
+                write!(f, "crossing on a non-number at {pos:?}")
+            }
+            PlacementError::ParallelOverlap(pos) => write!(f, "parallel overlap at {pos:?}"),
+            PlacementError::TouchingNeighbour(pos) => write!(f, "touching neighbour at {pos:?}"),
+            PlacementError::OutOfBounds => write!(f, "layout exceeds the size constraints"),
+            PlacementError::InvalidEquation => write!(f, "equation does not hold"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct OccupiedCell {
+    symbol: Symbol,
+    horizontal: bool,
+    vertical: bool,
+}
+
+impl OccupiedCell {
+    fn occupied_in(&self, direction: &Direction) -> bool {
+        match direction {
+            Direction::Horizontal => self.horizontal,
+            Direction::Vertical => self.vertical,
+        }
+    }
+}
+
+/// Keeps track of every cell used by the equations placed so far and decides
+/// whether another `GridEquation` can be added without overlapping them.
+pub struct PlacementGrid {
+    generator: EquationGenerator,
+    constraints: LayoutConstraints,
+    /// Between 0 and 1. The denser, the more candidates are generated per
+    /// step and the likelier the ones crossing the most equations are tried
+    /// first.
+    crossing_density: f64,
+    cells: HashMap<(i32, i32), OccupiedCell>,
+    equations: Vec<GridEquation>,
+    best: Vec<GridEquation>,
+}
+
+impl PlacementGrid {
+    pub fn new(
+        generator: EquationGenerator,
+        constraints: LayoutConstraints,
+        crossing_density: f64,
+    ) -> Self {
+        Self {
+            generator,
+            constraints,
+            crossing_density,
+            cells: HashMap::new(),
+            equations: Vec::new(),
+            best: Vec::new(),
+        }
+    }
+
+    pub fn equations(&self) -> &[GridEquation] {
+        &self.equations
+    }
+
+    pub fn check(&self, candidate: &GridEquation) -> Result<(), PlacementError> {
+        if !candidate.eq.holds() {
+            return Err(PlacementError::InvalidEquation);
+        }
+
+        let bounds = Bounds::of(self.equations.iter().chain(std::iter::once(candidate)));
+        if !bounds.is_some_and(|bounds| self.constraints.allows(&bounds)) {
+            return Err(PlacementError::OutOfBounds);
+        }
+
+        let cells = candidate.cells();
+
+        for (pos, symbol) in cells.iter() {
+            if let Some(cell) = self.cells.get(pos) {
+                if cell.occupied_in(&candidate.direction) {
+                    return Err(PlacementError::ParallelOverlap(*pos));
+                }
+                match (&cell.symbol, symbol) {
+                    (Symbol::Number(a), Symbol::Number(b)) if a == b => {}
+                    (Symbol::Number(_), Symbol::Number(_)) => {
+                        return Err(PlacementError::Conflict(*pos));
+                    }
+                    _ => return Err(PlacementError::NonNumberCrossing(*pos)),
+                }
+                continue;
+            }
+
+            for neighbour in side_neighbours(*pos, &candidate.direction) {
+                if self.cells.contains_key(&neighbour) {
+                    return Err(PlacementError::TouchingNeighbour(neighbour));
+                }
+            }
+        }
+
+        // Nothing may directly continue the equation at either end.
+        let before = candidate.pos_at(-1);
+        let after = candidate.pos_at(candidate.len() as i32);
+        for pos in [before, after] {
+            if self.cells.contains_key(&pos) {
+                return Err(PlacementError::TouchingNeighbour(pos));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn place(&mut self, candidate: GridEquation) -> Result<(), PlacementError> {
+        self.check(&candidate)?;
+        self.occupy(&candidate);
+        self.equations.push(candidate);
+
+        if self.equations.len() > self.best.len() {
+            self.best = self.equations.clone();
+        }
+        Ok(())
+    }
+
+    /// Removes the most recently placed equation.
+    pub fn pop(&mut self) -> Option<GridEquation> {
+        let removed = self.equations.pop()?;
+
+        // Crossing cells are shared, so rebuild the occupancy from scratch.
+        self.cells.clear();
+        for eq in self.equations.clone().iter() {
+            self.occupy(eq);
+        }
+        Some(removed)
+    }
+
+    fn occupy(&mut self, eq: &GridEquation) {
+        for (pos, symbol) in eq.cells() {
+            let cell = self.cells.entry(pos).or_insert(OccupiedCell {
+                symbol,
+                horizontal: false,
+                vertical: false,
+            });
+            match eq.direction {
+                Direction::Horizontal => cell.horizontal = true,
+                Direction::Vertical => cell.vertical = true,
+            }
+        }
+    }
+
+    /// Number of cells `candidate` shares with the equations placed so far.
+    pub fn crossings(&self, candidate: &GridEquation) -> usize {
+        candidate
+            .cells()
+            .iter()
+            .filter(|(pos, _)| self.cells.contains_key(pos))
+            .count()
+    }
+
+    /// Places equations until `target` is reached, backtracking whenever no
+    /// candidate fits. Gives up once `budget` candidates have been tried.
+    fn fill(&mut self, target: usize, budget: &mut usize, rng: &mut impl Rng) -> bool {
+        if self.equations.len() >= target {
+            return true;
+        }
+
+        // Crossing several equations at once is rare, so denser layouts need
+        // more candidates to choose from.
+        let extra = (DENSE_CANDIDATES_PER_STEP - CANDIDATES_PER_STEP) as f64;
+        let num_candidates = CANDIDATES_PER_STEP + (extra * self.crossing_density) as usize;
+
+        let mut candidates = Vec::new();
+        for _ in 0..num_candidates {
+            if *budget == 0 {
+                break;
+            }
+            *budget -= 1;
+
+            let candidate = self.next_candidate(rng);
+            candidates.extend(candidate.filter(|candidate| self.check(candidate).is_ok()));
+        }
+
+        if rng.random_bool(self.crossing_density) {
+            candidates.sort_by_key(|candidate| Reverse(self.crossings(candidate)));
+        }
+
+        for candidate in candidates {
+            if self.place(candidate).is_ok() {
+                if self.fill(target, budget, rng) {
+                    return true;
+                }
+                self.pop();
+            }
+        }
+
+        false
+    }
+
+    // Generates an equation containing a random number of an equation already
+    // placed and lines it up perpendicular to it, so that both share that
+    // number. Any occurrence of the number may be used, which lets the layout
+    // grow up and to the left as well.
+    fn next_candidate(&self, rng: &mut impl Rng) -> Option<GridEquation> {
+        let anchor = self.equations.choose(rng)?;
+        let pos = anchor.pos_of_rand_number(rng)?;
+        let Some(Symbol::Number(n)) = anchor.get_symbol(pos) else {
+            return None;
+        };
+
+        let eq = self.generator.generate_equation(Some(*n), rng)?;
+        let offsets: Vec<usize> = eq
+            .symbols()
+            .iter()
+            .enumerate()
+            .filter(|(_, symbol)| *symbol == &Symbol::Number(*n))
+            .map(|(i, _)| i)
+            .collect();
+        let offset = *offsets.choose(rng)? as i32;
+
+        let mut candidate = GridEquation::new(eq, pos, anchor.direction.perpendicular());
+        let (start_x, start_y) = candidate.pos_at(-offset);
+        candidate.translate(start_x - pos.0, start_y - pos.1);
+        Some(candidate)
+    }
+}
+
+fn side_neighbours(pos: (i32, i32), direction: &Direction) -> [(i32, i32); 2] {
+    let (x, y) = pos;
+    match direction {
+        Direction::Horizontal => [(x, y - 1), (x, y + 1)],
+        Direction::Vertical => [(x - 1, y), (x + 1, y)],
+    }
+}
+
+/// Shifts the layout so that its bounding box starts at (0, 0).
+pub fn normalize(equations: &mut [GridEquation]) {
+    let Some(bounds) = Bounds::of(equations.iter()) else {
+        return;
+    };
+    let (min_x, min_y) = bounds.min;
+    for eq in equations.iter_mut() {
+        eq.translate(-min_x, -min_y);
+    }
+}
+
+/// Generates `num_equations` crossing equations that never overlap, normalized
+/// to start at (0, 0). Falls back to the largest layout found if the target
+/// could not be reached within the constraints, and gives up if not even the
+/// first equation could be generated and fit them.
+pub fn generate_layout(
+    num_equations: usize,
+    generator: &EquationGenerator,
+    constraints: &LayoutConstraints,
+    crossing_density: f64,
+    rng: &mut impl Rng,
+) -> Option<Vec<GridEquation>> {
+    let mut best: Vec<GridEquation> = Vec::new();
+
+    for _ in 0..MAX_RESTARTS {
+        let mut grid = PlacementGrid::new(generator.clone(), constraints.clone(), crossing_density);
+        let Some(first) = generator.generate_equation(None, rng) else {
+            continue;
+        };
+        if grid
+            .place(GridEquation::new(first, (0, 0), Direction::Horizontal))
+            .is_err()
+        {
+            continue;
+        }
+
+        let mut budget = MAX_STEPS;
+        if grid.fill(num_equations, &mut budget, rng) {
+            best = grid.equations().to_vec();
+            break;
+        }
+
+        if grid.best.len() > best.len() {
+            best = grid.best;
+        }
+    }
+
+    if best.is_empty() {
+        return None;
+    }
+    if best.len() < num_equations {
+        warn!(
+            "could only place {} of {} equations",
+            best.len(),
+            num_equations
+        );
+    }
+
+    normalize(&mut best);
+    Some(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+    use crate::equation::Operator;
+    use crate::equation::test_util::equation;
+
+    // A grid holding `2 + 3 = 5` along the top row, from (0, 0) to (4, 0).
+    fn grid() -> PlacementGrid {
+        let mut grid = PlacementGrid::new(
+            EquationGenerator::default(),
+            LayoutConstraints::default(),
+            0.0,
+        );
+        grid.place(equation(
+            2,
+            Operator::Add,
+            3,
+            5,
+            (0, 0),
+            Direction::Horizontal,
+        ))
+        .unwrap();
+        grid
+    }
+
+    #[test]
+    fn crosses_on_a_shared_number() {
+        let mut grid = grid();
+        let crossing = equation(3, Operator::Multiply, 2, 6, (2, 0), Direction::Vertical);
+        assert_eq!(grid.crossings(&crossing), 1);
+        assert_eq!(grid.place(crossing), Ok(()));
+
+        // Also from above, ending on the shared number.
+        let above = equation(1, Operator::Add, 4, 5, (4, -4), Direction::Vertical);
+        assert_eq!(grid.place(above), Ok(()));
+        assert_eq!(grid.equations().len(), 3);
+    }
+
+    #[test]
+    fn rejects_invalid_crossings() {
+        let grid = grid();
+        assert_eq!(
+            grid.check(&equation(
+                4,
+                Operator::Multiply,
+                2,
+                8,
+                (2, 0),
+                Direction::Vertical
+            )),
+            Err(PlacementError::Conflict((2, 0)))
+        );
+        assert_eq!(
+            grid.check(&equation(
+                1,
+                Operator::Add,
+                1,
+                2,
+                (1, -4),
+                Direction::Vertical
+            )),
+            Err(PlacementError::NonNumberCrossing((1, 0)))
+        );
+        assert_eq!(
+            grid.check(&equation(
+                5,
+                Operator::Subtract,
+                1,
+                4,
+                (4, 0),
+                Direction::Horizontal
+            )),
+            Err(PlacementError::ParallelOverlap((4, 0)))
+        );
+        assert_eq!(
+            grid.check(&equation(
+                1,
+                Operator::Add,
+                1,
+                2,
+                (0, 1),
+                Direction::Vertical
+            )),
+            Err(PlacementError::TouchingNeighbour((0, 0)))
+        );
+        assert_eq!(
+            grid.check(&equation(
+                1,
+                Operator::Add,
+                1,
+                3,
+                (2, 0),
+                Direction::Vertical
+            )),
+            Err(PlacementError::InvalidEquation)
+        );
+    }
+
+    #[test]
+    fn respects_constraints() {
+        let mut grid = PlacementGrid::new(
+            EquationGenerator::default(),
+            LayoutConstraints {
+                max_width: Some(5),
+                max_height: Some(3),
+            },
+            0.0,
+        );
+        let first = equation(2, Operator::Add, 3, 5, (0, 0), Direction::Horizontal);
+        assert_eq!(grid.place(first), Ok(()));
+        assert_eq!(
+            grid.check(&equation(
+                3,
+                Operator::Multiply,
+                2,
+                6,
+                (2, 0),
+                Direction::Vertical
+            )),
+            Err(PlacementError::OutOfBounds)
+        );
+    }
+
+    #[test]
+    fn gives_up_when_the_first_equation_does_not_fit() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let constraints = LayoutConstraints {
+            max_width: Some(3),
+            max_height: Some(3),
+        };
+        let layout = generate_layout(
+            8,
+            &EquationGenerator::default(),
+            &constraints,
+            0.5,
+            &mut rng,
+        );
+        assert!(layout.is_none());
+    }
+
+    #[test]
+    fn generated_layouts_never_overlap() {
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let layout = generate_layout(
+                8,
+                &EquationGenerator::default(),
+                &LayoutConstraints::default(),
+                0.5,
+                &mut rng,
+            )
+            .unwrap();
+            assert_eq!(
+                Bounds::of(layout.iter()).map(|bounds| bounds.min),
+                Some((0, 0))
+            );
+
+            // Every equation fits onto the ones before it.
+            let mut grid = PlacementGrid::new(
+                EquationGenerator::default(),
+                LayoutConstraints::default(),
+                0.0,
+            );
+            for eq in layout {
+                assert_eq!(grid.place(eq), Ok(()), "seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_layout() {
+        let generate = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            generate_layout(
+                8,
+                &EquationGenerator::default(),
+                &LayoutConstraints::default(),
+                0.5,
+                &mut rng,
+            )
+            .unwrap()
+            .iter()
+            .map(|eq| eq.to_string())
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(generate(42), generate(42));
+        assert_ne!(generate(1), generate(2));
+    }
+}
@@ -1,35 +1,291 @@
-use crate::board;
-use crate::board::Cover;
+use crate::board::{Board, Coordinates, EquationStatus, TILE_DEPTH};
+use crate::equation::{Operator, Symbol};
+use crate::game_state::InGame;
+use crate::history::ActionKind;
+use crate::number::Number;
+use crate::ui::UiFocus;
+use bevy::ecs::system::SystemParam;
+use bevy::input::ButtonState;
+use bevy::input::keyboard::{Key, KeyboardInput};
 use bevy::prelude::*;
 
+const CURSOR_COLOR: Color = Color::srgb(0.25, 0.5, 0.95);
+// Longest text typed into a cell, like `-12/25`.
+const MAX_TYPED_LEN: usize = 8;
+// Characters of numbers and operators. Other keys are left to shortcuts.
+const TYPEABLE: &str = "0123456789-+*/^.√log";
+
+/// The cell the keyboard acts on. Moved with the arrow keys or WASD, or by
+/// clicking a cell.
+#[derive(Resource, Debug, Default)]
+pub struct Selection {
+    pub cell: Option<Coordinates>,
+    /// What was typed into the selected cell since it was selected.
+    pub typed: String,
+    /// Whether the typed text was entered into the cell yet. Later keys amend
+    /// that entry, so the whole text is undone at once.
+    pub entered: bool,
+}
+
+impl Selection {
+    pub fn select(&mut self, cell: Coordinates) {
+        self.cell = Some(cell);
+        self.typed.clear();
+        self.entered = false;
+    }
+}
+
+/// Outline drawn behind the selected cell.
+#[derive(Component)]
+pub struct SelectionCursor;
+
+/// The camera the board is drawn with, which cells are picked through.
+#[derive(Component)]
+pub struct BoardCamera;
+
+/// Finds the board cell under the mouse cursor through the `BoardCamera`, so
+/// picking follows the camera as it pans and zooms.
+#[derive(SystemParam)]
+pub struct CursorPicker<'w> {
+    window: Single<'w, &'static Window>,
+    camera: Single<'w, (&'static Camera, &'static GlobalTransform), With<BoardCamera>>,
+}
+
+impl CursorPicker<'_> {
+    /// Position of the cursor in the window, in logical pixels.
+    pub fn cursor_position(&self) -> Option<Vec2> {
+        self.window.cursor_position()
+    }
+
+    /// The point of the board plane under the cursor. Works for 2D and 3D
+    /// cameras alike: the ray through the cursor is cut with the plane the
+    /// cells lie in.
+    pub fn world_position(&self) -> Option<Vec2> {
+        let cursor_position = self.cursor_position()?;
+        let (camera, camera_transform) = *self.camera;
+        let ray = camera
+            .viewport_to_world(camera_transform, cursor_position)
+            .ok()?;
+        let distance = ray.intersect_plane(Vec3::ZERO, InfinitePlane3d::new(Vec3::Z))?;
+        Some(ray.get_point(distance).truncate())
+    }
+
+    /// The board cell under the cursor, if any.
+    pub fn cell(&self, board: &Board) -> Option<Coordinates> {
+        board.coordinates_at(self.world_position()?)
+    }
+}
+
+/// Clicking a cell selects it. Right clicking a blank also sends its tile back
+/// to the rack.
 pub fn handle_mouse_click(
-    mut commands: Commands,
-    children: Query<(Entity, &ChildOf), With<Cover>>,
-    coordinates: Query<&board::Coordinates>,
     mouse_button_input: Res<ButtonInput<MouseButton>>,
-    window: Single<&Window>,
+    picker: CursorPicker,
+    board: Option<ResMut<Board>>,
+    mut selection: ResMut<Selection>,
 ) {
-    let half_win_size = window.size() / 2.0;
+    let Some(mut board) = board else {
+        return;
+    };
+    let left = mouse_button_input.just_pressed(MouseButton::Left);
+    let right = mouse_button_input.just_pressed(MouseButton::Right);
+    if !left && !right {
+        return;
+    }
 
-    if mouse_button_input.just_pressed(MouseButton::Left) {
-        let mut cursor_position_centered = window.cursor_position().unwrap() - half_win_size;
-        cursor_position_centered.y *= -1.0; // Invert y-axis for the Bevy coordinate system
+    let Some(coordinates) = picker.cell(&board) else {
+        return;
+    };
+    if board.symbol(coordinates) == Some(&Symbol::Empty) {
+        return;
+    }
+    selection.select(coordinates);
+    if right {
+        board.edit(ActionKind::Play, [(coordinates, None)]);
+    }
+}
 
-        // TODO: Put this in a a constant/resource
-        let grid_size = 60;
-        let grid_padding = 14;
+/// Moves the selection with the arrow keys and WASD, skipping empty cells.
+/// Typed numbers and operators go into the selected blank, Backspace clears
+/// it and Tab jumps to the next unsolved equation.
+pub fn handle_keyboard(
+    mut keyboard_input: EventReader<KeyboardInput>,
+    board: Option<ResMut<Board>>,
+    mut selection: ResMut<Selection>,
+    ui_focus: Res<UiFocus>,
+) {
+    let Some(mut board) = board else {
+        keyboard_input.clear();
+        return;
+    };
+    if ui_focus.keyboard {
+        keyboard_input.clear();
+        return;
+    }
 
-        let cell_span = (grid_size + grid_padding) as f32;
-        let x = ((cursor_position_centered.x + cell_span / 2.0) / cell_span).floor() as i32;
-        let y = ((cursor_position_centered.y + cell_span / 2.0) / cell_span).floor() as i32;
+    for event in keyboard_input.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
 
-        for (child, child_of) in children.iter() {
-            if let Ok(coordinate) = coordinates.get(child_of.parent()) {
-                if coordinate.x == x && coordinate.y == y {
-                    commands.entity(child).despawn();
-                    break;
+        let direction = match event.key_code {
+            KeyCode::ArrowUp | KeyCode::KeyW => Some((0, -1)),
+            KeyCode::ArrowDown | KeyCode::KeyS => Some((0, 1)),
+            KeyCode::ArrowLeft | KeyCode::KeyA => Some((-1, 0)),
+            KeyCode::ArrowRight | KeyCode::KeyD => Some((1, 0)),
+            _ => None,
+        };
+        if let Some(direction) = direction {
+            let next = match selection.cell {
+                Some(cell) => step(&board, cell, direction),
+                None => first_cell(&board),
+            };
+            if let Some(next) = next {
+                selection.select(next);
+            }
+            continue;
+        }
+
+        match &event.logical_key {
+            Key::Tab => {
+                if let Some(next) = next_unsolved(&board, selection.cell) {
+                    selection.select(next);
+                }
+            }
+            Key::Backspace | Key::Delete => {
+                selection.typed.clear();
+                selection.entered = false;
+                if let Some(cell) = selection.cell {
+                    board.edit(ActionKind::Play, [(cell, None)]);
                 }
             }
+            Key::Character(text) => {
+                let Some(cell) = selection.cell.filter(|cell| board.is_blank(*cell)) else {
+                    continue;
+                };
+                if !text.chars().all(|c| TYPEABLE.contains(c))
+                    || selection.typed.len() + text.len() > MAX_TYPED_LEN
+                {
+                    continue;
+                }
+                selection.typed.push_str(text);
+                if let Some(symbol) = parse_symbol(&selection.typed) {
+                    if selection.entered {
+                        board.amend(cell, Some(symbol));
+                    } else {
+                        selection.entered = board.edit(ActionKind::Play, [(cell, Some(symbol))]);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Shows the cursor behind the selected cell.
+pub fn update_cursor(
+    selection: Res<Selection>,
+    board: Option<Res<Board>>,
+    cursor: Single<(&mut Transform, &mut Visibility), With<SelectionCursor>>,
+) {
+    let (mut transform, mut visibility) = cursor.into_inner();
+    let selected = board.as_ref().zip(selection.cell).filter(|(board, cell)| {
+        board
+            .symbol(*cell)
+            .is_some_and(|symbol| *symbol != Symbol::Empty)
+    });
+    let Some((board, cell)) = selected else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+
+    let size = (board.cell_size.size + board.cell_size.padding / 2) as f32;
+    // Behind the tile, so only its rim shows.
+    transform.translation = board.cell_position(cell).extend(-TILE_DEPTH);
+    transform.scale = Vec3::new(size, size, 1.0);
+    *visibility = Visibility::Visible;
+}
+
+pub fn setup_cursor(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    commands.spawn((
+        Name::new("Selection cursor"),
+        Mesh3d(meshes.add(Rectangle::new(1.0, 1.0))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: CURSOR_COLOR,
+            unlit: true,
+            ..default()
+        })),
+        Transform::default(),
+        Visibility::Hidden,
+        SelectionCursor,
+        StateScoped(InGame),
+    ));
+}
+
+// Reads what was typed into a cell. A lone "-" is the operator, "-3" the
+// number.
+fn parse_symbol(text: &str) -> Option<Symbol> {
+    if let Ok(number) = text.parse::<Number>() {
+        return Some(Symbol::Number(number));
+    }
+    Operator::all()
+        .into_iter()
+        .find(|operator| operator.to_string() == text)
+        .map(Symbol::Operator)
+}
+
+// The next cell from `from` in `direction` that is not empty, if any is left
+// before the edge of the board.
+fn step(board: &Board, from: Coordinates, (dx, dy): (i32, i32)) -> Option<Coordinates> {
+    let mut cell = from;
+    loop {
+        cell = Coordinates::new(cell.x + dx, cell.y + dy);
+        match board.symbol(cell)? {
+            Symbol::Empty => continue,
+            _ => return Some(cell),
         }
     }
 }
+
+fn first_cell(board: &Board) -> Option<Coordinates> {
+    board.equations.first().map(|eq| {
+        let (x, y) = eq.pos_at(0);
+        Coordinates::new(x, y)
+    })
+}
+
+// The first empty blank, or else the first blank, of the next equation after
+// the one through `from` that is not solved yet.
+fn next_unsolved(board: &Board, from: Option<Coordinates>) -> Option<Coordinates> {
+    let count = board.equations.len();
+    let current = from.and_then(|cell| {
+        board
+            .equations
+            .iter()
+            .position(|eq| eq.contains_point((cell.x, cell.y)))
+    });
+    let start = current.map_or(0, |i| i + 1);
+
+    (0..count)
+        .map(|offset| (start + offset) % count)
+        .filter(|i| board.statuses[*i] != EquationStatus::Solved)
+        .find_map(|i| {
+            let eq = &board.equations[i];
+            let blanks: Vec<Coordinates> = (0..eq.len())
+                .map(|offset| {
+                    let (x, y) = eq.pos_at(offset as i32);
+                    Coordinates::new(x, y)
+                })
+                .filter(|cell| board.is_blank(*cell))
+                .collect();
+            blanks
+                .iter()
+                .find(|cell| !board.entries.contains_key(cell))
+                .or(blanks.first())
+                .copied()
+        })
+}
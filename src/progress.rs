@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::time::Stopwatch;
+
+use crate::board::{Board, EquationStatus};
+use crate::game_state::{self, GameState, MenuButton};
+
+/// Sent when the last blank of an equation is filled and it holds.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct EquationSolved {
+    pub equation: usize,
+}
+
+/// Sent when a solved equation is edited, or an equation is filled in wrong.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct EquationBroken {
+    pub equation: usize,
+    pub status: EquationStatus,
+}
+
+/// Sent when every equation of the board is solved.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PuzzleCompleted {
+    pub time: Duration,
+    pub moves: u32,
+    pub hints: u32,
+}
+
+/// Time spent on the current puzzle.
+#[derive(Resource, Debug, Default)]
+pub struct PuzzleClock(pub Stopwatch);
+
+/// Starts the clock of a new puzzle.
+pub fn start_clock(mut commands: Commands) {
+    commands.insert_resource(PuzzleClock::default());
+}
+
+pub fn tick_clock(time: Res<Time>, board: Res<Board>, mut clock: ResMut<PuzzleClock>) {
+    if !board.is_solved() {
+        clock.0.tick(time.delta());
+    }
+}
+
+/// Sends the status changes recorded by edits of the board as events.
+pub fn send_board_events(
+    mut board: ResMut<Board>,
+    clock: Res<PuzzleClock>,
+    mut solved: EventWriter<EquationSolved>,
+    mut broken: EventWriter<EquationBroken>,
+    mut completed: EventWriter<PuzzleCompleted>,
+) {
+    if board.changes.is_empty() {
+        return;
+    }
+    // Taking the changes is not an edit of the board.
+    let changes = std::mem::take(&mut board.bypass_change_detection().changes);
+
+    for change in changes.iter() {
+        info!("equation {} is {:?}", change.equation, change.to);
+        if change.to == EquationStatus::Solved {
+            solved.write(EquationSolved {
+                equation: change.equation,
+            });
+        } else if change.from == EquationStatus::Solved || change.to == EquationStatus::Wrong {
+            broken.write(EquationBroken {
+                equation: change.equation,
+                status: change.to,
+            });
+        }
+    }
+
+    if board.is_solved()
+        && changes
+            .iter()
+            .any(|change| change.to == EquationStatus::Solved)
+    {
+        let event = PuzzleCompleted {
+            time: clock.0.elapsed(),
+            moves: board.moves,
+            hints: board.hints,
+        };
+        info!(
+            "puzzle completed in {:.0?} with {} moves and {} hints",
+            event.time, event.moves, event.hints
+        );
+        completed.write(event);
+    }
+}
+
+/// Shows the time and the moves it took to complete the puzzle.
+pub fn show_completion_screen(mut commands: Commands, board: Res<Board>, clock: Res<PuzzleClock>) {
+    let seconds = clock.0.elapsed().as_secs();
+    game_state::spawn_menu(
+        &mut commands,
+        GameState::Results,
+        "Puzzle solved!",
+        Some(format!(
+            "Time {}:{:02}, {} moves, {} hints",
+            seconds / 60,
+            seconds % 60,
+            board.moves,
+            board.hints
+        )),
+        &[
+            (MenuButton::NewGame, "New game"),
+            (MenuButton::MainMenu, "Main menu"),
+        ],
+    );
+}
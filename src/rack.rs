@@ -0,0 +1,221 @@
+use bevy::prelude::*;
+
+use crate::board::{Board, Coordinates};
+use crate::equation::Symbol;
+use crate::game_state::InGame;
+use crate::history::ActionKind;
+use crate::player_input::CursorPicker;
+
+const RACK_TILE_SIZE: f32 = 48.0;
+const RACK_TILE_COLOR: Color = Color::srgb(0.95, 0.88, 0.7);
+const RACK_TEXT_COLOR: Color = Color::srgb(0.15, 0.1, 0.05);
+
+/// The row of tiles along the bottom of the screen.
+#[derive(Component)]
+pub struct Rack;
+
+/// A tile in the rack.
+#[derive(Component)]
+pub struct RackTile {
+    pub symbol: Symbol,
+}
+
+/// Follows the cursor while a tile is dragged.
+#[derive(Component)]
+pub struct DragGhost;
+
+#[derive(Debug, Clone)]
+pub struct DraggedTile {
+    pub symbol: Symbol,
+    /// The cell the tile was taken from, or `None` for the rack.
+    pub from: Option<Coordinates>,
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct TileDrag(pub Option<DraggedTile>);
+
+pub fn setup_rack(mut commands: Commands) {
+    commands.spawn((
+        Name::new("Rack"),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(16.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            flex_wrap: FlexWrap::Wrap,
+            column_gap: Val::Px(8.0),
+            row_gap: Val::Px(8.0),
+            ..default()
+        },
+        // Tracks whether the cursor is over the rack
+        Interaction::default(),
+        Rack,
+        StateScoped(InGame),
+    ));
+
+    commands.spawn((
+        Name::new("Drag ghost"),
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Px(RACK_TILE_SIZE),
+            height: Val::Px(RACK_TILE_SIZE),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            display: Display::None,
+            ..default()
+        },
+        BackgroundColor(RACK_TILE_COLOR.with_alpha(0.8)),
+        BorderRadius::all(Val::Px(6.0)),
+        GlobalZIndex(10),
+        DragGhost,
+        StateScoped(InGame),
+        children![(Text::new(""), TextColor(RACK_TEXT_COLOR))],
+    ));
+}
+
+/// Fills the rack with the tiles that are not on the board. A tile dragged out
+/// of the rack is left out until it is dropped.
+pub fn update_rack(
+    mut commands: Commands,
+    board: Res<Board>,
+    drag: Res<TileDrag>,
+    rack: Single<Entity, With<Rack>>,
+) {
+    let mut tiles = board.remaining_tiles();
+    let dragged = match &drag.0 {
+        Some(DraggedTile { symbol, from: None }) => tiles.iter().position(|tile| tile == symbol),
+        _ => None,
+    };
+    if let Some(i) = dragged {
+        tiles.remove(i);
+    }
+
+    commands
+        .entity(*rack)
+        .despawn_related::<Children>()
+        .with_children(|rack| {
+            for symbol in tiles {
+                rack.spawn((
+                    Button,
+                    Node {
+                        width: Val::Px(RACK_TILE_SIZE),
+                        height: Val::Px(RACK_TILE_SIZE),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    BackgroundColor(RACK_TILE_COLOR),
+                    BorderRadius::all(Val::Px(6.0)),
+                    children![(Text::new(symbol.to_string()), TextColor(RACK_TEXT_COLOR))],
+                    RackTile { symbol },
+                ));
+            }
+        });
+}
+
+/// Picks up a tile from the rack, or the tile entered in a blank of the board.
+pub fn start_drag(
+    mouse_button_input: Res<ButtonInput<MouseButton>>,
+    picker: CursorPicker,
+    board: Option<Res<Board>>,
+    rack_tiles: Query<(&Interaction, &RackTile)>,
+    mut drag: ResMut<TileDrag>,
+) {
+    if !mouse_button_input.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(board) = board else {
+        return;
+    };
+
+    // The rack is drawn on top of the board, so it goes first.
+    if let Some((_, tile)) = rack_tiles
+        .iter()
+        .find(|(interaction, _)| **interaction == Interaction::Pressed)
+    {
+        drag.0 = Some(DraggedTile {
+            symbol: tile.symbol.clone(),
+            from: None,
+        });
+        return;
+    }
+
+    let Some(coordinates) = picker.cell(&board) else {
+        return;
+    };
+    if let Some(symbol) = board.entries.get(&coordinates) {
+        drag.0 = Some(DraggedTile {
+            symbol: symbol.clone(),
+            from: Some(coordinates),
+        });
+    }
+}
+
+pub fn move_ghost(
+    window: Single<&Window>,
+    drag: Res<TileDrag>,
+    ghost: Single<(&mut Node, &Children), With<DragGhost>>,
+    mut texts: Query<&mut Text>,
+) {
+    let (mut node, children) = ghost.into_inner();
+    let (Some(dragged), Some(cursor_position)) = (&drag.0, window.cursor_position()) else {
+        if node.display != Display::None {
+            node.display = Display::None;
+        }
+        return;
+    };
+
+    node.display = Display::Flex;
+    node.left = Val::Px(cursor_position.x - RACK_TILE_SIZE / 2.0);
+    node.top = Val::Px(cursor_position.y - RACK_TILE_SIZE / 2.0);
+    for child in children.iter() {
+        if let Ok(mut text) = texts.get_mut(child) {
+            text.0 = dragged.symbol.to_string();
+        }
+    }
+}
+
+/// Drops the dragged tile. On a blank, any tile already there is swapped into
+/// the cell the dragged tile came from, or goes back to the rack. Anywhere
+/// else, the dragged tile goes back to the rack.
+pub fn end_drag(
+    mouse_button_input: Res<ButtonInput<MouseButton>>,
+    picker: CursorPicker,
+    board: Option<ResMut<Board>>,
+    rack: Single<&Interaction, With<Rack>>,
+    rack_tiles: Query<&Interaction, With<RackTile>>,
+    mut drag: ResMut<TileDrag>,
+) {
+    if !mouse_button_input.just_released(MouseButton::Left) || drag.0.is_none() {
+        return;
+    }
+    let Some(dragged) = drag.0.take() else {
+        return;
+    };
+    let Some(mut board) = board else {
+        return;
+    };
+
+    let over_rack = **rack != Interaction::None
+        || rack_tiles
+            .iter()
+            .any(|interaction| *interaction != Interaction::None);
+    let target = picker
+        .cell(&board)
+        .filter(|coordinates| board.is_blank(*coordinates) && !over_rack);
+    match (target, dragged.from) {
+        (Some(target), Some(from)) if target == from => {}
+        (Some(target), from) => {
+            let previous = board.entries.get(&target).cloned();
+            let swapped = from.map(|from| (from, previous));
+            board.edit(
+                ActionKind::Play,
+                swapped.into_iter().chain([(target, Some(dragged.symbol))]),
+            );
+        }
+        (None, Some(from)) => {
+            board.edit(ActionKind::Play, [(from, None)]);
+        }
+        (None, None) => {}
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, Coordinates};
+use crate::difficulty::{self, Difficulty, DifficultyProfile, PuzzleRating};
+use crate::equation::{GridEquation, GridEquations, PuzzleSeed, Symbol};
+use crate::game_state::GameState;
+use crate::history::History;
+use crate::progress::PuzzleClock;
+use crate::solver::HiddenCells;
+
+// Saves of any other version are ignored. Bump whenever `SavedGame` changes.
+const SAVE_VERSION: u32 = 1;
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A game in progress, as written to storage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedGame {
+    pub version: u32,
+    pub seed: u64,
+    pub profile: DifficultyProfile,
+    /// The solved puzzle, which holds the solutions of the hidden cells.
+    pub equations: Vec<GridEquation>,
+    pub hidden: BTreeSet<(i32, i32)>,
+    /// What the player entered into the blanks.
+    pub entries: Vec<((i32, i32), Symbol)>,
+    pub elapsed: Duration,
+    pub moves: u32,
+    pub history: History,
+    pub hints: u32,
+}
+
+impl SavedGame {
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    pub fn from_ron(text: &str) -> Option<SavedGame> {
+        let saved: SavedGame = match ron::from_str(text) {
+            Ok(saved) => saved,
+            Err(err) => {
+                warn!("ignoring unreadable saved game: {}", err);
+                return None;
+            }
+        };
+        if saved.version != SAVE_VERSION {
+            warn!(
+                "ignoring saved game of version {}, expected {}",
+                saved.version, SAVE_VERSION
+            );
+            return None;
+        }
+        Some(saved)
+    }
+}
+
+/// A saved game that is set up instead of generating a new puzzle.
+#[derive(Resource, Debug)]
+pub struct ResumedGame(pub SavedGame);
+
+/// Resumes the game in storage, if any.
+pub fn load_game(
+    mut commands: Commands,
+    mut seed: ResMut<PuzzleSeed>,
+    mut profile: ResMut<DifficultyProfile>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(saved) = storage::read().and_then(|text| SavedGame::from_ron(&text)) else {
+        return;
+    };
+    info!("resuming game with seed {}", saved.seed);
+
+    let score = difficulty::rate(&saved.equations, &saved.hidden);
+    *seed = PuzzleSeed(saved.seed);
+    *profile = saved.profile.clone();
+    commands.insert_resource(GridEquations(saved.equations.clone()));
+    commands.insert_resource(HiddenCells(saved.hidden.clone()));
+    commands.insert_resource(PuzzleRating {
+        score,
+        difficulty: Difficulty::of_score(score),
+    });
+    commands.insert_resource(ResumedGame(saved));
+    next_state.set(GameState::Generating);
+}
+
+/// Fills the board of a resumed game with the saved entries, moves, hints
+/// and time.
+pub fn resume_game(
+    mut commands: Commands,
+    resumed: Res<ResumedGame>,
+    mut board: ResMut<Board>,
+    mut clock: ResMut<PuzzleClock>,
+) {
+    let saved = &resumed.0;
+    for ((x, y), symbol) in saved.entries.iter() {
+        board.enter(Coordinates::new(*x, *y), Some(symbol.clone()));
+    }
+    // Restoring the entries is not playing.
+    board.moves = saved.moves;
+    board.changes.clear();
+    board.history = saved.history.clone();
+    board.hints = saved.hints;
+    clock.0.set_elapsed(saved.elapsed);
+    commands.remove_resource::<ResumedGame>();
+}
+
+pub fn save_game(
+    board: Res<Board>,
+    clock: Res<PuzzleClock>,
+    seed: Res<PuzzleSeed>,
+    profile: Res<DifficultyProfile>,
+) {
+    let mut entries: Vec<((i32, i32), Symbol)> = board
+        .entries
+        .iter()
+        .map(|(coordinates, symbol)| ((coordinates.x, coordinates.y), symbol.clone()))
+        .collect();
+    entries.sort_by_key(|(cell, _)| *cell);
+
+    let hidden = board
+        .equations
+        .iter()
+        .flat_map(|eq| eq.cells())
+        .map(|(cell, _)| cell)
+        .filter(|(x, y)| board.is_blank(Coordinates::new(*x, *y)))
+        .collect();
+
+    let saved = SavedGame {
+        version: SAVE_VERSION,
+        seed: **seed,
+        profile: profile.clone(),
+        equations: board.equations.clone(),
+        hidden,
+        entries,
+        elapsed: clock.0.elapsed(),
+        moves: board.moves,
+        history: board.history.clone(),
+        hints: board.hints,
+    };
+    let result = saved
+        .to_ron()
+        .map_err(|err| err.to_string())
+        .and_then(|text| storage::write(&text));
+    if let Err(err) = result {
+        error!("could not save the game: {}", err);
+    }
+}
+
+/// A completed puzzle is not resumed.
+pub fn delete_save() {
+    storage::remove();
+}
+
+// Native builds keep the save in the data directory of the user.
+#[cfg(not(target_arch = "wasm32"))]
+mod storage {
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn save_path() -> Option<PathBuf> {
+        let data_dir = if cfg!(target_os = "windows") {
+            std::env::var_os("APPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join("Library/Application Support"))
+        } else {
+            std::env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .or_else(|| {
+                    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+                })
+        };
+        Some(data_dir?.join("crossequa").join("save.ron"))
+    }
+
+    pub fn read() -> Option<String> {
+        fs::read_to_string(save_path()?).ok()
+    }
+
+    pub fn write(text: &str) -> Result<(), String> {
+        let path = save_path().ok_or("no data directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        fs::write(path, text).map_err(|err| err.to_string())
+    }
+
+    pub fn remove() {
+        if let Some(path) = save_path() {
+            // There is nothing to remove if no game was saved.
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// The web build keeps the save in the localStorage of the page.
+#[cfg(target_arch = "wasm32")]
+mod storage {
+    const KEY: &str = "crossequa-save";
+
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    pub fn read() -> Option<String> {
+        local_storage()?.get_item(KEY).ok()?
+    }
+
+    pub fn write(text: &str) -> Result<(), String> {
+        local_storage()
+            .ok_or("no local storage")?
+            .set_item(KEY, text)
+            .map_err(|err| format!("{:?}", err))
+    }
+
+    pub fn remove() {
+        if let Some(storage) = local_storage() {
+            let _ = storage.remove_item(KEY);
+        }
+    }
+}
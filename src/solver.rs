@@ -0,0 +1,491 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bevy::prelude::*;
+use rand::Rng;
+use rand::prelude::SliceRandom;
+
+use crate::equation::{Equation, GridEquation, Symbol};
+
+// Upper bound on the number of partial fillings tried for a single count.
+const MAX_SEARCH_NODES: usize = 100_000;
+
+/// Cells whose symbols the player has to find.
+#[derive(Resource, Debug, Clone, Default, Deref)]
+pub struct HiddenCells(pub BTreeSet<(i32, i32)>);
+
+// A cell of an equation as the solver sees it.
+#[derive(Debug, Clone)]
+enum Slot {
+    Known(Symbol),
+    /// Index of a hidden cell.
+    Hidden(usize),
+}
+
+#[derive(Debug, Clone)]
+struct EquationSlots {
+    lhs: Vec<Slot>,
+    rhs: Vec<Slot>,
+    /// Hidden cells of the equation.
+    hidden: Vec<usize>,
+}
+
+/// How a hidden cell was deduced, from the easiest technique to the hardest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Technique {
+    /// The cell is the last empty cell of an equation.
+    LastCell,
+    /// Only one tile fits the cell within one of its equations.
+    Equation,
+    /// Only one tile fits all equations through the cell at once.
+    Crossing,
+    /// Nothing could be deduced, so the cell had to be guessed.
+    Guess,
+}
+
+#[derive(Debug, Clone)]
+pub struct Deduction {
+    pub cell: (i32, i32),
+    pub symbol: Symbol,
+    pub technique: Technique,
+    /// Indices of the equations the cell was deduced from.
+    pub equations: Vec<usize>,
+}
+
+/// Counts the ways to fill the hidden cells of a board such that every
+/// equation holds. The symbols of the hidden cells form the pool of tiles to
+/// fill them with, so every tile is used exactly once.
+pub struct Solver {
+    equations: Vec<EquationSlots>,
+    /// Position and symbol of each hidden cell.
+    cells: Vec<((i32, i32), Symbol)>,
+    /// Equations through each hidden cell.
+    equations_of: Vec<Vec<usize>>,
+    /// Distinct symbols of the pool and how many of each are left.
+    pool: Vec<(Symbol, usize)>,
+    /// Order in which the hidden cells are filled.
+    order: Vec<usize>,
+    /// Equations whose last hidden cell is filled at each step.
+    completed_at: Vec<Vec<usize>>,
+    /// Pool index currently assigned to each hidden cell.
+    assignment: Vec<Option<usize>>,
+}
+
+impl Solver {
+    pub fn new(grid_equations: &[GridEquation], hidden: &BTreeSet<(i32, i32)>) -> Self {
+        let index_of: BTreeMap<(i32, i32), usize> = hidden
+            .iter()
+            .enumerate()
+            .map(|(i, pos)| (*pos, i))
+            .collect();
+        let mut hidden_symbols: Vec<Option<Symbol>> = vec![None; hidden.len()];
+
+        let mut equations = Vec::new();
+        for grid_equation in grid_equations {
+            let mut slot = |offset: usize, symbol: &Symbol| -> Slot {
+                match index_of.get(&grid_equation.pos_at(offset as i32)) {
+                    Some(i) => {
+                        hidden_symbols[*i] = Some(symbol.clone());
+                        Slot::Hidden(*i)
+                    }
+                    None => Slot::Known(symbol.clone()),
+                }
+            };
+            let eq = &grid_equation.eq;
+            let lhs: Vec<Slot> = eq
+                .lhs()
+                .iter()
+                .enumerate()
+                .map(|(i, s)| slot(i, s))
+                .collect();
+            let rhs: Vec<Slot> = eq
+                .rhs()
+                .iter()
+                .enumerate()
+                .map(|(i, s)| slot(eq.lhs().len() + 1 + i, s))
+                .collect();
+            let hidden = lhs
+                .iter()
+                .chain(rhs.iter())
+                .filter_map(|slot| match slot {
+                    Slot::Hidden(i) => Some(*i),
+                    Slot::Known(_) => None,
+                })
+                .collect();
+            equations.push(EquationSlots { lhs, rhs, hidden });
+        }
+
+        let mut equations_of = vec![Vec::new(); hidden.len()];
+        for (i, eq) in equations.iter().enumerate() {
+            for cell in eq.hidden.iter() {
+                equations_of[*cell].push(i);
+            }
+        }
+
+        // Cells outside of every equation have nothing to be deduced from.
+        let cells: Vec<((i32, i32), Symbol)> = hidden
+            .iter()
+            .zip(hidden_symbols.iter())
+            .map(|(pos, symbol)| (*pos, symbol.clone().unwrap_or(Symbol::Empty)))
+            .collect();
+
+        let mut pool: Vec<(Symbol, usize)> = Vec::new();
+        for symbol in hidden_symbols.into_iter().flatten() {
+            match pool.iter_mut().find(|(s, _)| *s == symbol) {
+                Some((_, count)) => *count += 1,
+                None => pool.push((symbol, 1)),
+            }
+        }
+
+        // Fill the equations with the fewest hidden cells first, so that
+        // wrong fillings are rejected as early as possible.
+        let mut by_hidden_count: Vec<usize> = (0..equations.len()).collect();
+        by_hidden_count.sort_by_key(|i| equations[*i].hidden.len());
+
+        let mut order: Vec<usize> = Vec::new();
+        for i in by_hidden_count.iter() {
+            for cell in equations[*i].hidden.iter() {
+                if !order.contains(cell) {
+                    order.push(*cell);
+                }
+            }
+        }
+
+        let mut completed_at = vec![Vec::new(); order.len()];
+        for (i, eq) in equations.iter().enumerate() {
+            let last_step = eq
+                .hidden
+                .iter()
+                .filter_map(|cell| order.iter().position(|c| c == cell))
+                .max();
+            if let Some(step) = last_step {
+                completed_at[step].push(i);
+            }
+        }
+
+        Self {
+            equations,
+            cells,
+            equations_of,
+            pool,
+            order,
+            completed_at,
+            assignment: vec![None; hidden.len()],
+        }
+    }
+
+    /// Counts the solutions, stopping once `limit` have been found. If the
+    /// search gives up before that, `limit` is returned as well, since more
+    /// solutions cannot be ruled out.
+    pub fn count_solutions(&mut self, limit: usize) -> usize {
+        self.count_solutions_within(limit, MAX_SEARCH_NODES)
+    }
+
+    fn count_solutions_within(&mut self, limit: usize, mut budget: usize) -> usize {
+        let mut found = 0;
+        self.search(0, limit, &mut found, &mut budget);
+        found
+    }
+
+    pub fn is_unique(&mut self) -> bool {
+        self.count_solutions(2) == 1
+    }
+
+    fn search(&mut self, step: usize, limit: usize, found: &mut usize, budget: &mut usize) {
+        if step == self.order.len() {
+            *found += 1;
+            return;
+        }
+        if *budget == 0 {
+            *found = limit;
+            return;
+        }
+        *budget -= 1;
+
+        let cell = self.order[step];
+        for tile in 0..self.pool.len() {
+            if self.pool[tile].1 == 0 {
+                continue;
+            }
+            self.pool[tile].1 -= 1;
+            self.assignment[cell] = Some(tile);
+
+            if self.completed_at[step].iter().all(|eq| self.holds(*eq)) {
+                self.search(step + 1, limit, found, budget);
+            }
+
+            self.pool[tile].1 += 1;
+            if *found >= limit {
+                break;
+            }
+        }
+        self.assignment[cell] = None;
+    }
+
+    /// Fills the hidden cells one by one the way a player would, always
+    /// using the easiest technique that determines a cell. Cells that cannot
+    /// be deduced are guessed right.
+    pub fn deductions(&mut self) -> Vec<Deduction> {
+        let mut deductions = Vec::new();
+        while let Some((cell, tile, technique, equations)) = self.next_deduction() {
+            self.pool[tile].1 -= 1;
+            self.assignment[cell] = Some(tile);
+            deductions.push(Deduction {
+                cell: self.cells[cell].0,
+                symbol: self.pool[tile].0.clone(),
+                technique,
+                equations,
+            });
+        }
+
+        // Leave the solver as it was for further counts.
+        for cell in 0..self.assignment.len() {
+            if let Some(tile) = self.assignment[cell].take() {
+                self.pool[tile].1 += 1;
+            }
+        }
+        deductions
+    }
+
+    /// The easiest deduction to start with.
+    pub fn first_deduction(&mut self) -> Option<Deduction> {
+        let (cell, tile, technique, equations) = self.next_deduction()?;
+        Some(Deduction {
+            cell: self.cells[cell].0,
+            symbol: self.pool[tile].0.clone(),
+            technique,
+            equations,
+        })
+    }
+
+    // The easiest cell to deduce next, as (cell, tile, technique, equations
+    // it is deduced from).
+    fn next_deduction(&mut self) -> Option<(usize, usize, Technique, Vec<usize>)> {
+        let mut best: Option<(usize, usize, Technique, Vec<usize>)> = None;
+        // Open cell with the fewest candidates, to guess if nothing else works
+        let mut fewest: Option<(usize, usize)> = None;
+
+        for cell in 0..self.cells.len() {
+            if self.assignment[cell].is_some() {
+                continue;
+            }
+
+            let mut candidates: Vec<usize> = (0..self.pool.len())
+                .filter(|tile| self.pool[*tile].1 > 0)
+                .collect();
+            let mut technique: Option<(Technique, usize)> = None;
+            for eq in self.equations_of[cell].clone() {
+                let fits: Vec<usize> = (0..self.pool.len())
+                    .filter(|tile| self.fits(eq, cell, *tile))
+                    .collect();
+                if fits.len() == 1 {
+                    let open = self.equations[eq]
+                        .hidden
+                        .iter()
+                        .filter(|c| self.assignment[**c].is_none())
+                        .count();
+                    let found = if open == 1 {
+                        Technique::LastCell
+                    } else {
+                        Technique::Equation
+                    };
+                    if technique.is_none_or(|(t, _)| found < t) {
+                        technique = Some((found, eq));
+                    }
+                }
+                candidates.retain(|tile| fits.contains(tile));
+            }
+
+            if candidates.len() == 1 {
+                let (technique, equations) = match technique {
+                    Some((technique, eq)) => (technique, vec![eq]),
+                    None => (Technique::Crossing, self.equations_of[cell].clone()),
+                };
+                if best.as_ref().is_none_or(|(_, _, t, _)| technique < *t) {
+                    best = Some((cell, candidates[0], technique, equations));
+                }
+            }
+            if fewest.is_none_or(|(_, count)| candidates.len() < count) {
+                fewest = Some((cell, candidates.len()));
+            }
+        }
+
+        if best.is_some() {
+            return best;
+        }
+        let (cell, _) = fewest?;
+        let tile = self
+            .pool
+            .iter()
+            .position(|(symbol, count)| *count > 0 && *symbol == self.cells[cell].1)?;
+        Some((
+            cell,
+            tile,
+            Technique::Guess,
+            self.equations_of[cell].clone(),
+        ))
+    }
+
+    // Whether `tile` can go into `cell` such that the other open cells of
+    // `eq` can still be filled to make it hold.
+    fn fits(&mut self, eq: usize, cell: usize, tile: usize) -> bool {
+        if self.pool[tile].1 == 0 {
+            return false;
+        }
+        let open: Vec<usize> = self.equations[eq]
+            .hidden
+            .iter()
+            .copied()
+            .filter(|c| *c != cell && self.assignment[*c].is_none())
+            .collect();
+
+        self.pool[tile].1 -= 1;
+        self.assignment[cell] = Some(tile);
+        let fits = self.satisfiable(eq, &open);
+        self.assignment[cell] = None;
+        self.pool[tile].1 += 1;
+        fits
+    }
+
+    fn satisfiable(&mut self, eq: usize, open: &[usize]) -> bool {
+        let Some((cell, rest)) = open.split_first() else {
+            return self.holds(eq);
+        };
+
+        for tile in 0..self.pool.len() {
+            if self.pool[tile].1 == 0 {
+                continue;
+            }
+            self.pool[tile].1 -= 1;
+            self.assignment[*cell] = Some(tile);
+            let satisfiable = self.satisfiable(eq, rest);
+            self.assignment[*cell] = None;
+            self.pool[tile].1 += 1;
+            if satisfiable {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn holds(&self, eq: usize) -> bool {
+        let fill = |slots: &[Slot]| -> Vec<Symbol> {
+            slots
+                .iter()
+                .map(|slot| match slot {
+                    Slot::Known(symbol) => symbol.clone(),
+                    Slot::Hidden(i) => {
+                        let tile = self.assignment[*i].expect("completed equations are filled");
+                        self.pool[tile].0.clone()
+                    }
+                })
+                .collect()
+        };
+        let eq = &self.equations[eq];
+        Equation::new(fill(&eq.lhs), fill(&eq.rhs)).holds()
+    }
+}
+
+// Only numbers and operators are hidden. Parentheses and the equals sign
+// give the structure of an equation away anyway.
+fn is_hideable(symbol: &Symbol) -> bool {
+    matches!(symbol, Symbol::Number(_) | Symbol::Operator(_))
+}
+
+/// Picks up to `count` cells to hide, such that the puzzle keeps exactly one
+/// solution. Cells are tried in random order and skipped if hiding them would
+/// make the puzzle ambiguous.
+pub fn pick_hidden_cells(
+    equations: &[GridEquation],
+    count: usize,
+    rng: &mut impl Rng,
+) -> BTreeSet<(i32, i32)> {
+    let candidates: BTreeSet<(i32, i32)> = equations
+        .iter()
+        .flat_map(|eq| eq.cells())
+        .filter(|(_, symbol)| is_hideable(symbol))
+        .map(|(pos, _)| pos)
+        .collect();
+    let mut candidates: Vec<(i32, i32)> = candidates.into_iter().collect();
+    candidates.shuffle(rng);
+
+    let mut hidden = BTreeSet::new();
+    for pos in candidates {
+        if hidden.len() >= count {
+            break;
+        }
+        hidden.insert(pos);
+        if !Solver::new(equations, &hidden).is_unique() {
+            hidden.remove(&pos);
+        }
+    }
+
+    if hidden.len() < count {
+        warn!("could only hide {} of {} cells", hidden.len(), count);
+    }
+    hidden
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::equation::test_util::equation;
+    use crate::equation::{Direction, Operator};
+
+    // `2 + 3 = 5` along the top row, crossed at the 3 by `3 * 2 = 6` going
+    // down.
+    fn crossing() -> Vec<GridEquation> {
+        vec![
+            equation(2, Operator::Add, 3, 5, (0, 0), Direction::Horizontal),
+            equation(3, Operator::Multiply, 2, 6, (2, 0), Direction::Vertical),
+        ]
+    }
+
+    fn hidden(cells: &[(i32, i32)]) -> BTreeSet<(i32, i32)> {
+        cells.iter().copied().collect()
+    }
+
+    #[test]
+    fn counts_swapped_operands_as_another_solution() {
+        let equations = &crossing()[..1];
+        let mut solver = Solver::new(equations, &hidden(&[(0, 0), (2, 0)]));
+        assert_eq!(solver.count_solutions(10), 2);
+        assert!(!solver.is_unique());
+
+        let mut solver = Solver::new(equations, &hidden(&[(0, 0), (1, 0)]));
+        assert_eq!(solver.count_solutions(10), 1);
+        assert!(solver.is_unique());
+    }
+
+    #[test]
+    fn crossing_equation_makes_the_solution_unique() {
+        let mut solver = Solver::new(&crossing(), &hidden(&[(0, 0), (2, 0)]));
+        assert_eq!(solver.count_solutions(10), 1);
+
+        let deductions = solver.deductions();
+        assert_eq!(deductions.len(), 2);
+        assert!(deductions.iter().all(|d| d.technique != Technique::Guess));
+    }
+
+    #[test]
+    fn gives_up_when_the_budget_runs_out() {
+        let equations = &crossing()[..1];
+        let mut solver = Solver::new(equations, &hidden(&[(0, 0), (1, 0)]));
+        assert_eq!(solver.count_solutions_within(2, 1), 2);
+        // Giving up leaves the solver as it was.
+        assert_eq!(solver.count_solutions(2), 1);
+    }
+
+    #[test]
+    fn hidden_cells_keep_the_puzzle_unique() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+
+        let equations = crossing();
+        for seed in 0..10 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let hidden = pick_hidden_cells(&equations, 4, &mut rng);
+            assert!(!hidden.is_empty());
+            assert!(Solver::new(&equations, &hidden).is_unique());
+        }
+    }
+}
@@ -1,39 +1,290 @@
+use std::collections::{BTreeMap, HashMap};
+
 use bevy::{
-    asset::{AssetServer, Handle},
-    image::Image,
+    asset::{AssetServer, Handle, LoadState},
+    image::{Image, ImageLoaderSettings},
     pbr::StandardMaterial,
+    prelude::*,
     utils::default,
 };
+use serde::Deserialize;
+
+// The manifest is built in, so the web build needs no file access for it.
+const MANIFEST: &str = include_str!("../assets/materials.ron");
+
+/// The look of a tile. Every kind has its own material, tinted with its
+/// colour over the textures of the theme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TileKind {
+    Number,
+    Operator,
+    Equals,
+    Blank,
+    /// A blank whose equations are all solved.
+    Solved,
+    /// A tile of an equation that does not hold.
+    Wrong,
+    /// A tile of an equation the hint shown is about.
+    Hint,
+}
+
+impl TileKind {
+    pub fn all() -> [TileKind; 7] {
+        [
+            TileKind::Number,
+            TileKind::Operator,
+            TileKind::Equals,
+            TileKind::Blank,
+            TileKind::Solved,
+            TileKind::Wrong,
+            TileKind::Hint,
+        ]
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            TileKind::Number => Color::srgb(0.9, 0.8, 0.6),
+            TileKind::Operator => Color::srgb(0.85, 0.72, 0.5),
+            TileKind::Equals => Color::srgb(0.78, 0.66, 0.46),
+            TileKind::Blank => Color::srgb(0.98, 0.96, 0.9),
+            TileKind::Solved => Color::srgb(0.6, 0.85, 0.6),
+            TileKind::Wrong => Color::srgb(0.9, 0.5, 0.5),
+            TileKind::Hint => Color::srgb(0.55, 0.75, 0.95),
+        }
+    }
+}
+
+/// Textures of a PBR material set, relative to the assets folder.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MaterialSet {
+    pub base_color: Option<String>,
+    pub metallic_roughness: Option<String>,
+    pub emissive: Option<String>,
+    pub occlusion: Option<String>,
+    pub normal_map: Option<String>,
+    pub metallic: f32,
+    pub perceptual_roughness: f32,
+    /// How bright the emissive texture glows. Dark by default.
+    #[serde(default)]
+    pub emissive_intensity: f32,
+}
+
+impl MaterialSet {
+    fn textures(&self) -> Vec<(TextureSlot, &str)> {
+        [
+            (TextureSlot::BaseColor, &self.base_color),
+            (TextureSlot::MetallicRoughness, &self.metallic_roughness),
+            (TextureSlot::Emissive, &self.emissive),
+            (TextureSlot::Occlusion, &self.occlusion),
+            (TextureSlot::NormalMap, &self.normal_map),
+        ]
+        .into_iter()
+        .filter_map(|(slot, path)| Some((slot, path.as_deref()?)))
+        .collect()
+    }
+}
+
+/// The material sets that can be picked as theme, from
+/// `assets/materials.ron`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Manifest {
+    pub default_theme: String,
+    pub sets: BTreeMap<String, MaterialSet>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextureSlot {
+    BaseColor,
+    MetallicRoughness,
+    Emissive,
+    Occlusion,
+    NormalMap,
+}
+
+impl TextureSlot {
+    // Colours are stored in sRGB, data like normals linearly.
+    fn is_srgb(&self) -> bool {
+        matches!(self, TextureSlot::BaseColor | TextureSlot::Emissive)
+    }
+
+    fn apply(&self, material: &mut StandardMaterial, texture: Handle<Image>) {
+        let slot = match self {
+            TextureSlot::BaseColor => &mut material.base_color_texture,
+            TextureSlot::MetallicRoughness => &mut material.metallic_roughness_texture,
+            TextureSlot::Emissive => &mut material.emissive_texture,
+            TextureSlot::Occlusion => &mut material.occlusion_texture,
+            TextureSlot::NormalMap => &mut material.normal_map_texture,
+        };
+        *slot = Some(texture);
+    }
+}
 
+/// The tile materials of the selected theme. Materials start out in the flat
+/// colours of their kind, and take on each texture of the theme once it has
+/// loaded. Textures that fail to load are left out.
+#[derive(Resource)]
 pub struct TextureManager {
-    tile_material: StandardMaterial,
+    manifest: Manifest,
+    /// The material set the tiles are drawn with. Changing it loads the set.
+    pub theme: String,
+    loaded_theme: Option<String>,
+    materials: HashMap<TileKind, Handle<StandardMaterial>>,
+    /// Added to the brightness of the emissive texture, so the lighting can
+    /// make the panels glow.
+    pub glow: f32,
+    applied_glow: f32,
+    /// Textures of the theme that are still loading.
+    pending: Vec<(TextureSlot, Handle<Image>)>,
+}
+
+impl FromWorld for TextureManager {
+    fn from_world(world: &mut World) -> Self {
+        let manifest: Manifest =
+            ron::from_str(MANIFEST).expect("assets/materials.ron is a valid manifest");
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let materials = TileKind::all()
+            .into_iter()
+            .map(|kind| (kind, materials.add(kind.color())))
+            .collect();
+
+        TextureManager {
+            theme: manifest.default_theme.clone(),
+            manifest,
+            loaded_theme: None,
+            materials,
+            glow: 0.0,
+            applied_glow: 0.0,
+            pending: Vec::new(),
+        }
+    }
 }
 
 impl TextureManager {
-    pub fn new(asset_server: &AssetServer) -> Self {
-        let base_color_texture = asset_server.load("textures/Scifi_Panels_01_basecolor.png");
-        let metallic_roughtness_texture =
-            asset_server.load("textures/Scifi_Panels_01_roughness.png");
-        let emissive_texture = asset_server.load("textures/Scifi_Panels_01_emissive.png");
-        let occlusion_texture = asset_server.load("textures/Scifi_Panels_01_ambientocclusion.png");
-        let normal_map_texture = asset_server.load("textures/Scifi_Panels_01_normal.png");
-
-        let tile_material = StandardMaterial {
-            // vary key PBR parameters on a grid of spheres to show the effect
-            base_color_texture: Some(base_color_texture.clone()),
-            metallic_roughness_texture: Some(metallic_roughtness_texture.clone()),
-            emissive_texture: Some(emissive_texture.clone()),
-            occlusion_texture: Some(occlusion_texture.clone()),
-            normal_map_texture: Some(normal_map_texture.clone()),
-            metallic: 0.5,
-            perceptual_roughness: 0.5,
-            ..default()
+    pub fn themes(&self) -> impl Iterator<Item = &str> {
+        self.manifest.sets.keys().map(String::as_str)
+    }
+
+    pub fn material(&self, kind: TileKind) -> Handle<StandardMaterial> {
+        self.materials[&kind].clone()
+    }
+
+    /// Whether every texture of the theme has loaded or failed to.
+    pub fn is_loaded(&self) -> bool {
+        self.loaded_theme.as_ref() == Some(&self.theme) && self.pending.is_empty()
+    }
+
+    fn emissive(&self) -> LinearRgba {
+        let intensity = self
+            .manifest
+            .sets
+            .get(&self.theme)
+            .map_or(0.0, |set| set.emissive_intensity);
+        LinearRgba::WHITE * (intensity + self.glow)
+    }
+
+    // Resets the materials to the flat colours of the theme and starts
+    // loading its textures.
+    fn load_theme(&mut self, asset_server: &AssetServer, materials: &mut Assets<StandardMaterial>) {
+        self.loaded_theme = Some(self.theme.clone());
+        let Some(set) = self.manifest.sets.get(&self.theme) else {
+            warn!("no material set '{}', using flat colours", self.theme);
+            self.pending.clear();
+            return;
         };
 
-        TextureManager { tile_material }
+        for (kind, handle) in self.materials.iter() {
+            if let Some(material) = materials.get_mut(handle) {
+                *material = StandardMaterial {
+                    base_color: kind.color(),
+                    metallic: set.metallic,
+                    perceptual_roughness: set.perceptual_roughness,
+                    ..default()
+                };
+            }
+        }
+        self.pending = set
+            .textures()
+            .into_iter()
+            .map(|(slot, path)| {
+                let is_srgb = slot.is_srgb();
+                let texture = asset_server.load_with_settings(
+                    path.to_string(),
+                    move |settings: &mut ImageLoaderSettings| settings.is_srgb = is_srgb,
+                );
+                (slot, texture)
+            })
+            .collect();
     }
+}
+
+/// Loads the textures of the selected theme, and adds each to the tile
+/// materials once it has loaded. Brightens or dims the emissive textures when
+/// the glow changes.
+pub fn update_materials(
+    mut manager: ResMut<TextureManager>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if manager.glow != manager.applied_glow {
+        manager.applied_glow = manager.glow;
+        let emissive = manager.emissive();
+        for handle in manager.materials.values() {
+            if let Some(material) = materials
+                .get_mut(handle)
+                .filter(|material| material.emissive_texture.is_some())
+            {
+                material.emissive = emissive;
+            }
+        }
+    }
+    if manager.is_loaded() {
+        return;
+    }
+    if manager.loaded_theme.as_ref() != Some(&manager.theme) {
+        manager.load_theme(&asset_server, &mut materials);
+    }
+
+    let emissive = manager.emissive();
+    let pending = std::mem::take(&mut manager.pending);
+    for (slot, texture) in pending {
+        match asset_server.load_state(&texture) {
+            LoadState::Loaded => {
+                for handle in manager.materials.values() {
+                    if let Some(material) = materials.get_mut(handle) {
+                        slot.apply(material, texture.clone());
+                        if slot == TextureSlot::Emissive {
+                            material.emissive = emissive;
+                        }
+                    }
+                }
+            }
+            LoadState::Failed(err) => {
+                warn!("leaving out texture of theme '{}': {}", manager.theme, err);
+            }
+            _ => manager.pending.push((slot, texture)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn manifest_lists_existing_textures() {
+        let manifest: Manifest = ron::from_str(MANIFEST).unwrap();
+        assert!(manifest.sets.contains_key(&manifest.default_theme));
 
-    pub fn get_tile_material(&self) -> StandardMaterial {
-        return self.tile_material.clone();
+        let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+        for (name, set) in manifest.sets.iter() {
+            for (slot, path) in set.textures() {
+                assert!(
+                    assets.join(path).is_file(),
+                    "{slot:?} texture of {name} is missing: {path}"
+                );
+            }
+        }
     }
 }
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use bevy::math::curve::{Curve, EaseFunction};
+use bevy::prelude::*;
+
+use crate::board::{Board, Coordinates, EquationStatus, Tile};
+use crate::equation::Symbol;
+use crate::progress::{EquationBroken, EquationSolved, PuzzleCompleted};
+
+// Durations at normal speed, in seconds.
+const FLIP_DURATION: f32 = 0.35;
+const SHAKE_DURATION: f32 = 0.4;
+const GLOW_DURATION: f32 = 0.7;
+const HOP_DURATION: f32 = 0.45;
+// Delay between neighbouring cells of a ripple along an equation, and of the
+// cascade over the board.
+const RIPPLE_DELAY: f32 = 0.06;
+const CASCADE_DELAY: f32 = 0.04;
+
+const SHAKE_DISTANCE: f32 = 6.0;
+const SHAKE_TURNS: f32 = 3.0;
+// How much tiles grow at the top of a hop.
+const HOP_GROWTH: f32 = 0.2;
+
+const REVEAL_GLOW: LinearRgba = LinearRgba::rgb(0.5, 0.5, 0.5);
+const SOLVED_GLOW: LinearRgba = LinearRgba::rgb(0.3, 1.0, 0.4);
+const WRONG_GLOW: LinearRgba = LinearRgba::rgb(1.0, 0.2, 0.15);
+const COMPLETED_GLOW: LinearRgba = LinearRgba::rgb(1.0, 0.8, 0.3);
+
+/// How tiles are animated.
+#[derive(Resource, Debug, Clone)]
+pub struct AnimationSettings {
+    /// Multiplies the speed of all animations.
+    pub speed: f32,
+    /// Tiles only light up instead of moving.
+    pub reduced_motion: bool,
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            reduced_motion: false,
+        }
+    }
+}
+
+/// Progress of an animation that starts after a delay.
+#[derive(Debug, Clone)]
+struct Tween {
+    delay: f32,
+    duration: f32,
+    elapsed: f32,
+}
+
+impl Tween {
+    fn new(duration: f32, delay: f32) -> Self {
+        Self {
+            delay,
+            duration,
+            elapsed: 0.0,
+        }
+    }
+
+    fn tick(&mut self, delta: f32) {
+        self.elapsed += delta;
+    }
+
+    /// From 0 to 1 while running, `None` before it starts.
+    fn progress(&self) -> Option<f32> {
+        let elapsed = self.elapsed - self.delay;
+        (elapsed >= 0.0).then(|| (elapsed / self.duration).min(1.0))
+    }
+
+    fn finished(&self) -> bool {
+        self.elapsed >= self.delay + self.duration
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionKind {
+    /// Turns the tile face up, when a blank is filled.
+    Flip,
+    /// Shakes the tile sideways, when its equation is wrong.
+    Shake,
+    /// Lifts the tile for a moment, in the cascade over a completed puzzle.
+    Hop,
+}
+
+/// Moves a cell for a while, then puts it back where it belongs.
+#[derive(Component, Debug, Clone)]
+pub struct Motion {
+    kind: MotionKind,
+    tween: Tween,
+}
+
+impl Motion {
+    pub fn new(kind: MotionKind, delay: f32) -> Self {
+        let duration = match kind {
+            MotionKind::Flip => FLIP_DURATION,
+            MotionKind::Shake => SHAKE_DURATION,
+            MotionKind::Hop => HOP_DURATION,
+        };
+        Self {
+            kind,
+            tween: Tween::new(duration, delay),
+        }
+    }
+}
+
+/// Lights up the tile of a cell with an emissive colour that rises and
+/// fades again.
+#[derive(Component, Debug, Clone)]
+pub struct Glow {
+    color: LinearRgba,
+    tween: Tween,
+}
+
+impl Glow {
+    pub fn new(color: LinearRgba, delay: f32) -> Self {
+        Self {
+            color,
+            tween: Tween::new(GLOW_DURATION, delay),
+        }
+    }
+}
+
+/// The material of a glowing tile. Tiles share the material of their kind, so
+/// a glowing tile gets a copy of it for the time being.
+#[derive(Component)]
+pub struct GlowMaterial {
+    shared: Handle<StandardMaterial>,
+    own: Handle<StandardMaterial>,
+}
+
+/// Flips the blanks that were just filled, by the player, a hint or undo.
+pub fn start_reveals(
+    mut commands: Commands,
+    board: Res<Board>,
+    settings: Res<AnimationSettings>,
+    mut entries: Local<HashMap<Coordinates, Symbol>>,
+) {
+    // The entries of a resumed game are not revealed.
+    if !board.is_added() {
+        for (cell, symbol) in board.entries.iter() {
+            if entries.get(cell) == Some(symbol) {
+                continue;
+            }
+            let Some(entity) = board.cells.get(cell) else {
+                continue;
+            };
+            if settings.reduced_motion {
+                commands.entity(*entity).insert(Glow::new(REVEAL_GLOW, 0.0));
+            } else {
+                commands
+                    .entity(*entity)
+                    .insert(Motion::new(MotionKind::Flip, 0.0));
+            }
+        }
+    }
+    *entries = board.entries.clone();
+}
+
+/// Shakes wrong equations, lights up solved ones along their cells and
+/// cascades over the board when the puzzle is completed.
+pub fn start_feedback(
+    mut commands: Commands,
+    mut solved: EventReader<EquationSolved>,
+    mut broken: EventReader<EquationBroken>,
+    mut completed: EventReader<PuzzleCompleted>,
+    board: Res<Board>,
+    settings: Res<AnimationSettings>,
+) {
+    let cells_of = |equation: usize| -> Vec<Entity> {
+        let eq = &board.equations[equation];
+        (0..eq.len())
+            .filter_map(|i| {
+                let (x, y) = eq.pos_at(i as i32);
+                board.cells.get(&Coordinates::new(x, y)).copied()
+            })
+            .collect()
+    };
+
+    for event in broken.read() {
+        if event.status != EquationStatus::Wrong {
+            continue;
+        }
+        for entity in cells_of(event.equation) {
+            let mut cell = commands.entity(entity);
+            cell.insert(Glow::new(WRONG_GLOW, 0.0));
+            if !settings.reduced_motion {
+                cell.insert(Motion::new(MotionKind::Shake, 0.0));
+            }
+        }
+    }
+    for event in solved.read() {
+        for (i, entity) in cells_of(event.equation).into_iter().enumerate() {
+            commands
+                .entity(entity)
+                .insert(Glow::new(SOLVED_GLOW, i as f32 * RIPPLE_DELAY));
+        }
+    }
+    if completed.read().count() > 0 {
+        for (cell, entity) in board.cells.iter() {
+            let delay = (cell.x + cell.y) as f32 * CASCADE_DELAY;
+            let mut cell = commands.entity(*entity);
+            cell.insert(Glow::new(COMPLETED_GLOW, delay));
+            if !settings.reduced_motion {
+                cell.insert(Motion::new(MotionKind::Hop, delay));
+            }
+        }
+    }
+}
+
+/// Moves the cells through their motions, and puts them back in place once
+/// done.
+pub fn animate_motion(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<AnimationSettings>,
+    board: Res<Board>,
+    mut cells: Query<(Entity, &Coordinates, &mut Transform, &mut Motion)>,
+) {
+    for (entity, coordinates, mut transform, mut motion) in cells.iter_mut() {
+        motion.tween.tick(time.delta_secs() * settings.speed);
+        *transform = Transform::from_translation(board.cell_position(*coordinates).extend(0.0));
+        if motion.tween.finished() || settings.reduced_motion {
+            commands.entity(entity).remove::<Motion>();
+            continue;
+        }
+
+        let Some(t) = motion.tween.progress() else {
+            continue;
+        };
+        match motion.kind {
+            MotionKind::Flip => {
+                let turned = 1.0 - EaseFunction::CubicOut.sample_clamped(t);
+                transform.rotation = Quat::from_rotation_y(FRAC_PI_2 * turned);
+            }
+            MotionKind::Shake => {
+                transform.translation.x +=
+                    (t * SHAKE_TURNS * TAU).sin() * SHAKE_DISTANCE * (1.0 - t);
+            }
+            MotionKind::Hop => {
+                transform.scale = Vec3::splat(1.0 + HOP_GROWTH * (t * PI).sin());
+            }
+        }
+    }
+}
+
+type GlowingTile<'a> = (
+    Entity,
+    &'a mut MeshMaterial3d<StandardMaterial>,
+    Option<&'a GlowMaterial>,
+);
+
+/// Fades the glows of the cells in and out, and gives the tiles their shared
+/// material back once done.
+pub fn animate_glow(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<AnimationSettings>,
+    mut cells: Query<(Entity, &Children, &mut Glow)>,
+    mut tiles: Query<GlowingTile, With<Tile>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, children, mut glow) in cells.iter_mut() {
+        glow.tween.tick(time.delta_secs() * settings.speed);
+        let finished = glow.tween.finished();
+        if finished {
+            commands.entity(entity).remove::<Glow>();
+        }
+        let intensity = glow.tween.progress().map_or(0.0, |t| (t * PI).sin());
+
+        let mut children = tiles.iter_many_mut(children);
+        while let Some((tile, mut material, glow_material)) = children.fetch_next() {
+            // The tile may have been given the material of another kind since.
+            let shared = match glow_material {
+                Some(glow_material) if material.0 == glow_material.own => {
+                    glow_material.shared.clone()
+                }
+                _ => material.0.clone(),
+            };
+            if finished {
+                material.0 = shared;
+                commands.entity(tile).remove::<GlowMaterial>();
+                continue;
+            }
+
+            let Some(mut glowing) = materials.get(&shared).cloned() else {
+                continue;
+            };
+            glowing.emissive += glow.color * intensity;
+            let own = match glow_material {
+                Some(glow_material) => glow_material.own.clone(),
+                None => materials.add(glowing.clone()),
+            };
+            if let Some(own_material) = materials.get_mut(&own) {
+                *own_material = glowing;
+            }
+            material.0 = own.clone();
+            if glow_material.is_none_or(|glow_material| glow_material.shared != shared) {
+                commands.entity(tile).insert(GlowMaterial { shared, own });
+            }
+        }
+    }
+}
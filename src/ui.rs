@@ -0,0 +1,160 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+use crate::difficulty::{Difficulty, DifficultyProfile, PuzzleRating};
+use crate::equation::PuzzleSeed;
+use crate::lighting::{LightingPreset, SceneLighting};
+use crate::texture_manager::TextureManager;
+use crate::tile_animation::AnimationSettings;
+
+/// Whether the windows take keyboard or mouse input, so typing or clicking in
+/// them does not also play on the board.
+#[derive(Resource, Debug, Default)]
+pub struct UiFocus {
+    pub keyboard: bool,
+    pub pointer: bool,
+}
+
+/// Picks the theme of the tiles, the lighting and how tiles are animated.
+pub fn settings_window(
+    mut contexts: EguiContexts,
+    mut textures: ResMut<TextureManager>,
+    mut lighting: ResMut<SceneLighting>,
+    mut animation: ResMut<AnimationSettings>,
+) {
+    egui::Window::new("Settings").show(contexts.ctx_mut(), |ui| {
+        let mut theme = textures.theme.clone();
+        egui::ComboBox::from_label("Theme")
+            .selected_text(theme.as_str())
+            .show_ui(ui, |ui| {
+                for name in textures.themes() {
+                    ui.selectable_value(&mut theme, name.to_string(), name);
+                }
+            });
+        if theme != textures.theme {
+            textures.theme = theme;
+        }
+
+        let mut preset = lighting.preset;
+        egui::ComboBox::from_label("Lighting")
+            .selected_text(format!("{preset:?}"))
+            .show_ui(ui, |ui| {
+                for option in LightingPreset::all() {
+                    ui.selectable_value(&mut preset, option, format!("{option:?}"));
+                }
+            });
+        if preset != lighting.preset {
+            *lighting = SceneLighting::new(preset);
+        }
+        let mut shadows = lighting.shadows;
+        ui.checkbox(&mut shadows, "Shadows");
+        if shadows != lighting.shadows {
+            lighting.shadows = shadows;
+        }
+
+        let mut speed = animation.speed;
+        ui.add(egui::Slider::new(&mut speed, 0.25..=3.0).text("Animation speed"));
+        let mut reduced_motion = animation.reduced_motion;
+        ui.checkbox(&mut reduced_motion, "Reduced motion");
+        if speed != animation.speed || reduced_motion != animation.reduced_motion {
+            animation.speed = speed;
+            animation.reduced_motion = reduced_motion;
+        }
+    });
+}
+
+/// Debug panel for the exposure of the scene camera, toggled with F2. The
+/// exposure is set like that of a physical camera.
+pub fn lighting_window(mut contexts: EguiContexts, mut lighting: ResMut<SceneLighting>) {
+    egui::Window::new("Lighting").show(contexts.ctx_mut(), |ui| {
+        let mut exposure = lighting.exposure;
+        ui.add(
+            egui::Slider::new(&mut exposure.aperture_f_stops, 1.0..=22.0)
+                .logarithmic(true)
+                .text("Aperture (f-stops)"),
+        );
+        // Shown as the fraction of a second it usually is.
+        let mut shutter_speed = exposure.shutter_speed_s.recip();
+        let shutter = ui.add(
+            egui::Slider::new(&mut shutter_speed, 1.0..=1000.0)
+                .logarithmic(true)
+                .text("Shutter speed (1/s)"),
+        );
+        if shutter.changed() {
+            exposure.shutter_speed_s = shutter_speed.recip();
+        }
+        ui.add(
+            egui::Slider::new(&mut exposure.sensitivity_iso, 50.0..=6400.0)
+                .logarithmic(true)
+                .text("Sensitivity (ISO)"),
+        );
+        ui.label(format!("EV100 {:.1}", exposure.ev100()));
+        if exposure.aperture_f_stops != lighting.exposure.aperture_f_stops
+            || exposure.shutter_speed_s != lighting.exposure.shutter_speed_s
+            || exposure.sensitivity_iso != lighting.exposure.sensitivity_iso
+        {
+            lighting.exposure = exposure;
+        }
+
+        if ui.button("Reset").clicked() {
+            *lighting = SceneLighting::new(lighting.preset);
+        }
+    });
+}
+
+/// Shows the seed of the current puzzle and lets the player enter another one
+/// to replay or share a board, or roll a new random puzzle. The difficulty
+/// picks the `DifficultyProfile` of the next puzzle.
+pub fn seed_window(
+    mut contexts: EguiContexts,
+    mut seed: ResMut<PuzzleSeed>,
+    mut profile: ResMut<DifficultyProfile>,
+    rating: Option<Res<PuzzleRating>>,
+    mut ui_focus: ResMut<UiFocus>,
+    mut seed_text: Local<String>,
+) {
+    if seed.is_changed() {
+        *seed_text = seed.to_string();
+    }
+
+    let ctx = contexts.ctx_mut();
+    egui::Window::new("Puzzle").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Seed");
+            ui.text_edit_singleline(&mut *seed_text);
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Load").clicked() {
+                match seed_text.trim().parse() {
+                    Ok(value) => *seed = PuzzleSeed(value),
+                    Err(_) => warn!("'{}' is not a valid seed", seed_text.trim()),
+                }
+            }
+            if ui.button("New puzzle").clicked() {
+                *seed = PuzzleSeed(rand::random());
+            }
+        });
+
+        let mut target = profile.target;
+        egui::ComboBox::from_label("Difficulty")
+            .selected_text(format!("{target:?}"))
+            .show_ui(ui, |ui| {
+                for difficulty in Difficulty::all() {
+                    ui.selectable_value(&mut target, difficulty, format!("{difficulty:?}"));
+                }
+            });
+        if target != profile.target {
+            *profile = DifficultyProfile::preset(target);
+        }
+
+        if let Some(rating) = rating {
+            ui.label(format!(
+                "Rated {:?} (score {})",
+                rating.difficulty, rating.score
+            ));
+        }
+    });
+
+    ui_focus.keyboard = ctx.wants_keyboard_input();
+    ui_focus.pointer = ctx.wants_pointer_input() || ctx.is_pointer_over_area();
+}